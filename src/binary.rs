@@ -0,0 +1,126 @@
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use infer::MatcherType;
+use sha2::{Digest, Sha256};
+use crate::file_processing::NON_UTF8_PLACEHOLDER;
+
+/// How many bytes from the start of a file are inspected to decide whether it is binary.
+pub const SNIFF_LEN: usize = 8192;
+
+/// The MIME type reported for binary content without a recognised signature.
+pub const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Decides from the start of a file whether it is binary.
+///
+/// A file is binary if its first bytes contain a NUL byte, which text files
+/// practically never do, or if it begins with the magic number of a known
+/// non-text format (images, archives, executables, media, fonts and the like)
+/// and is not valid UTF-8. Many magic numbers are short ASCII strings, so text
+/// such as a README starting with `MZ-80` or notes starting with `BM` is never
+/// mistaken for an executable or a bitmap.
+///
+/// # Returns
+///
+/// * `Some(mime)` with the guessed MIME type if the content is binary.
+/// * `None` if the content looks like text.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    // A multi-byte character cut off at the end of the head is still valid
+    let has_nul = head.contains(&0);
+    let invalid_utf8 = std::str::from_utf8(head).is_err_and(|e| e.error_len().is_some());
+    if has_nul || invalid_utf8 {
+        if let Some(kind) = infer::get(head) {
+            if kind.matcher_type() != MatcherType::Text {
+                return Some(kind.mime_type());
+            }
+        }
+    }
+    has_nul.then_some(DEFAULT_MIME)
+}
+
+/// Guesses the MIME type of content already known to be binary.
+pub fn guess_mime(head: &[u8]) -> &'static str {
+    infer::get(head).map_or(DEFAULT_MIME, |kind| kind.mime_type())
+}
+
+/// Computes the SHA-256 digest of `prefix` followed by everything left in `rest`.
+///
+/// The remainder is streamed through a fixed-size buffer, so hashing a large
+/// file never holds more than its already-read prefix in memory.
+pub fn sha256_hex<R: Read>(prefix: &[u8], rest: &mut R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(prefix);
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = rest.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Formats the one-line placeholder written in place of binary content.
+pub fn placeholder(size: u64, mime: &str, sha256: &str) -> String {
+    format!("{} ({} bytes, {}, sha256:{})", NON_UTF8_PLACEHOLDER, size, mime, sha256)
+}
+
+/// Formats `bytes` as an `xxd`-style hex dump with 16 bytes per line.
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(dump, "{:08x}:", line * 16);
+        for pair in 0..8 {
+            dump.push(' ');
+            for byte in chunk.iter().skip(pair * 2).take(2) {
+                let _ = write!(dump, "{:02x}", byte);
+            }
+            // Pad the hex columns of a short final line so the ASCII column lines up
+            let missing = 2usize.saturating_sub(chunk.len().saturating_sub(pair * 2));
+            dump.push_str(&"  ".repeat(missing));
+        }
+        dump.push_str("  ");
+        dump.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        dump.push('\n');
+    }
+    dump
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"), Some("image/png"));
+        assert_eq!(sniff(b"PK\x03\x04\x14\x00\x00\x00"), Some("application/zip"));
+        assert_eq!(sniff(b"text\x00with a NUL"), Some(DEFAULT_MIME));
+        assert_eq!(sniff(b"fn main() {}\n"), None);
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?><a/>"), None);
+    }
+
+    #[test]
+    fn test_sniff_text_with_binary_magic() {
+        // ASCII text that happens to start with a short magic number stays text
+        assert_eq!(sniff(b"MZ-80 emulator\n\nA Sharp MZ-80K emulator.\n"), None);
+        assert_eq!(sniff(b"BMW notes\n- E30 timing belt\n"), None);
+        let pem = b"-----BEGIN CERTIFICATE-----\nMIIBszCCAVmgAwIBAgIUQ3dN\n-----END CERTIFICATE-----\n";
+        assert_eq!(sniff(pem), None);
+    }
+
+    #[test]
+    fn test_sha256_hex_streams_rest() {
+        let expected = sha256_hex(b"hello world", &mut io::empty()).unwrap();
+        assert_eq!(sha256_hex(b"hello", &mut &b" world"[..]).unwrap(), expected);
+        assert_eq!(expected, "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"\x89PNG\r\n\x1a\n0123456789abcdef");
+        assert_eq!(dump, concat!(
+            "00000000: 8950 4e47 0d0a 1a0a 3031 3233 3435 3637  .PNG....01234567\n",
+            "00000010: 3839 6162 6364 6566                      89abcdef\n",
+        ));
+    }
+}
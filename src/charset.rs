@@ -0,0 +1,119 @@
+use chardetng::{EncodingDetector, Iso2022JpDetection, Utf8Detection};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+
+/// Text decoded from a legacy encoding into UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcoded {
+    /// The decoded text.
+    pub text: String,
+    /// The name of the encoding the file was decoded from, e.g. `Shift_JIS`.
+    pub charset: &'static str,
+    /// Whether malformed sequences were replaced with U+FFFD.
+    pub lossy: bool,
+}
+
+/// Returns the UTF-16 encoding announced by a byte order mark at the start of `head`.
+///
+/// UTF-16 text is full of NUL bytes, so this has to be checked before the
+/// binary sniffing that would otherwise reject it. A UTF-8 BOM is not
+/// reported, as such files are already valid UTF-8.
+pub fn bom_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    match Encoding::for_bom(head) {
+        Some((encoding, _)) if encoding == UTF_16LE || encoding == UTF_16BE => Some(encoding),
+        _ => None,
+    }
+}
+
+/// Decodes `bytes` that are not valid UTF-8 from their legacy encoding.
+///
+/// When `bom` is given the content after the byte order mark is decoded as
+/// that encoding, otherwise the encoding is guessed with `chardetng`.
+///
+/// # Arguments
+///
+/// * `bytes` - The whole file, including any byte order mark.
+/// * `bom` - The encoding returned by `bom_encoding`, if any.
+/// * `lossy` - Whether malformed sequences may be replaced with U+FFFD
+///   instead of giving up.
+///
+/// # Returns
+///
+/// * `Some(Transcoded)` if the content decodes to plausible text.
+/// * `None` if it does not decode cleanly (and `lossy` is off), is empty
+///   after the byte order mark, or decodes to control characters that only
+///   appear in binary data.
+pub fn transcode(bytes: &[u8], bom: Option<&'static Encoding>, lossy: bool) -> Option<Transcoded> {
+    let (encoding, body) = match bom {
+        // Both UTF-16 byte order marks are two bytes long
+        Some(encoding) => (encoding, bytes.get(2..).unwrap_or_default()),
+        None => {
+            let mut detector = EncodingDetector::new(Iso2022JpDetection::Deny);
+            detector.feed(bytes, true);
+            (detector.guess(None, Utf8Detection::Deny), bytes)
+        }
+    };
+
+    if body.is_empty() {
+        return None;
+    }
+
+    let (text, lossy) = match encoding.decode_without_bom_handling_and_without_replacement(body) {
+        Some(text) => (text.into_owned(), false),
+        None if lossy => (encoding.decode_without_bom_handling(body).0.into_owned(), true),
+        None => return None,
+    };
+
+    // Control characters other than whitespace mean the bytes were not text after all
+    if text.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c')) {
+        return None;
+    }
+
+    Some(Transcoded { text, charset: encoding.name(), lossy })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    #[test]
+    fn test_bom_encoding() {
+        assert_eq!(bom_encoding(b"\xFF\xFEa\x00"), Some(UTF_16LE));
+        assert_eq!(bom_encoding(b"\xFE\xFF\x00a"), Some(UTF_16BE));
+        assert_eq!(bom_encoding(b"\xEF\xBB\xBFa"), None);
+        assert_eq!(bom_encoding(b"plain"), None);
+    }
+
+    #[test]
+    fn test_transcode_utf16_bom() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("héllo\n".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        let transcoded = transcode(&bytes, bom_encoding(&bytes), false).unwrap();
+        assert_eq!(transcoded, Transcoded { text: "héllo\n".to_string(), charset: "UTF-16LE", lossy: false });
+
+        // A BOM alone, or an odd number of bytes after it, is not UTF-16 text
+        assert_eq!(transcode(b"\xFF\xFE", Some(UTF_16LE), false), None);
+        assert_eq!(transcode(b"\xFF\xFE\xFD", Some(UTF_16LE), false), None);
+        assert!(transcode(b"\xFF\xFEa\x00\xFD", Some(UTF_16LE), true).unwrap().lossy);
+    }
+
+    #[test]
+    fn test_transcode_detected_charsets() {
+        let source = "// Café au lait, naïve façade, déjà vu, résumé.\nfn main() {}\n";
+        let bytes = WINDOWS_1252.encode(source).0;
+        let transcoded = transcode(&bytes, None, false).unwrap();
+        assert_eq!(transcoded.text, source);
+        assert_eq!(transcoded.charset, "windows-1252");
+
+        let source = "// これは日本語のコメントです。文字コードはシフトJISです。\nint main(void) { return 0; }\n";
+        let bytes = SHIFT_JIS.encode(source).0;
+        let transcoded = transcode(&bytes, None, false).unwrap();
+        assert_eq!(transcoded.text, source);
+        assert_eq!(transcoded.charset, "Shift_JIS");
+    }
+
+    #[test]
+    fn test_transcode_rejects_control_characters() {
+        assert_eq!(transcode(b"\x01\x02\x03\xFF\x04", None, true), None);
+    }
+}
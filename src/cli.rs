@@ -1,5 +1,6 @@
 use structopt::StructOpt;
 use std::path::PathBuf;
+use flatten::types::{BinaryPolicy, OutputFormat};
 
 /// Defines command-line arguments for the `flatten` application.
 ///
@@ -20,16 +21,20 @@ pub struct Cli {
     #[structopt(parse(from_os_str), short, long)]
     pub output: Option<PathBuf>,
 
-    /// Files or directories to exclude during flattening.
+    /// Paths, globs or `re:` regexes to exclude during flattening.
     ///
-    /// These paths will be ignored in the final output.
-    #[structopt(parse(from_os_str), short, long)]
+    /// Includes and excludes are checked in command-line order and the last
+    /// rule matching a path decides. Globs follow `.gitignore` conventions.
+    /// Repeat the flag for each pattern.
+    #[structopt(parse(from_os_str), short, long, number_of_values = 1)]
     pub exclude: Vec<PathBuf>,
 
-    /// Files or directories to include explicitly during flattening.
+    /// Paths, globs or `re:` regexes to include during flattening.
     ///
-    /// Only the files specified in this list will be processed.
-    #[structopt(parse(from_os_str), short, long)]
+    /// If any are given, only paths whose last matching rule is an include are
+    /// processed. A literal path is reached even if it is hidden or ignored.
+    /// Repeat the flag for each pattern.
+    #[structopt(parse(from_os_str), short, long, number_of_values = 1)]
     pub include: Vec<PathBuf>,
 
     /// Allow hidden files to be included in the output.
@@ -38,4 +43,267 @@ pub struct Cli {
     /// be processed.
     #[structopt(short, long)]
     pub allow_hidden: bool,
+
+    /// Do not read any ignore files.
+    ///
+    /// Implies every other `--no-*` ignore flag below.
+    #[structopt(long)]
+    pub no_ignore: bool,
+
+    /// Do not read `.flattenignore` files.
+    ///
+    /// These use gitignore syntax, apply in and outside git repositories, and
+    /// override every other ignore file.
+    #[structopt(long)]
+    pub no_flattenignore: bool,
+
+    /// Do not read `.ignore` files.
+    #[structopt(long)]
+    pub no_ignore_dot: bool,
+
+    /// Do not read `.gitignore` files.
+    #[structopt(long)]
+    pub no_gitignore: bool,
+
+    /// Do not read the global gitignore named by git's `core.excludesFile`.
+    #[structopt(long)]
+    pub no_global_gitignore: bool,
+
+    /// Do not read `.git/info/exclude`.
+    #[structopt(long)]
+    pub no_git_exclude: bool,
+
+    /// Do not read ignore files in the parent directories of the flattened directory.
+    #[structopt(long)]
+    pub no_ignore_parent: bool,
+
+    /// Only flatten files that differ from this commit, branch or tag.
+    ///
+    /// Committed, staged and unstaged changes since the revision all count,
+    /// as read from the local repository. Deleted files are listed on stderr.
+    /// Combines with `--staged` and `--untracked` to select their union.
+    #[structopt(long, value_name = "ref")]
+    pub changed_since: Option<String>,
+
+    /// Only flatten files with changes staged in the git index.
+    ///
+    /// The files are written as they are in the working copy, so edits made
+    /// after staging are included.
+    #[structopt(long)]
+    pub staged: bool,
+
+    /// Only flatten files git neither tracks nor ignores.
+    #[structopt(long)]
+    pub untracked: bool,
+
+    /// Flatten the directory as it is at this commit, branch or tag.
+    ///
+    /// The tree is read from the repository's object database without checking
+    /// it out, and that revision's ignore files apply along with the include
+    /// and exclude rules. The working copy and index are left untouched, and
+    /// symbolic links and submodules are skipped and listed on stderr.
+    #[structopt(long, value_name = "commit-ish", conflicts_with_all = &["changed-since", "staged", "untracked"])]
+    pub rev: Option<String>,
+
+    /// The layout of the output file: `markdown`, `json`, `jsonl`, `xml`, `html`,
+    /// `shar`, `patch`, `tar`, `tar.gz` or `zip`.
+    ///
+    /// `json` writes one document with a `files` array and `jsonl` writes one
+    /// record per line, each holding the path, syntax, size, encoding and content.
+    /// `xml` wraps each file in a `<document>` element for LLM prompts,
+    /// `html` renders a self-contained, syntax-highlighted report, `shar`
+    /// writes a `sh` script that recreates the files when run, and `patch`
+    /// writes a diff that `git apply` turns back into the files. `tar`, `tar.gz`
+    /// and `zip` bundle the raw files together with a `MANIFEST.json`.
+    #[structopt(short, long, default_value = "markdown", possible_values = OutputFormat::VARIANTS)]
+    pub format: OutputFormat,
+
+    /// The syntect theme used by `--format html`.
+    ///
+    /// Any of syntect's bundled themes may be named, e.g. `InspiredGitHub`,
+    /// `base16-ocean.dark` or `Solarized (light)`.
+    #[structopt(long)]
+    pub theme: Option<String>,
+
+    /// A template file defining a custom output layout.
+    ///
+    /// The template holds `{{#preamble}}`, `{{#header}}`, `{{#body}}` and
+    /// `{{#footer}}` sections with `{{variable}}` placeholders, and replaces
+    /// the layout selected by `--format`.
+    #[structopt(parse(from_os_str), long)]
+    pub template: Option<PathBuf>,
+
+    /// How binary files are written: `skip`, `placeholder`, `base64` or `hexdump`.
+    ///
+    /// Files with a NUL byte near the start, files that start with a known
+    /// binary magic number and are not valid UTF-8, and files in no detectable
+    /// text encoding are binary. `placeholder` records their size,
+    /// MIME guess and SHA-256 digest without keeping them in memory. The `shar`
+    /// and `patch` formats always embed binary files unless `skip` is given.
+    #[structopt(long, default_value = "placeholder", possible_values = BinaryPolicy::VARIANTS)]
+    pub binary: BinaryPolicy,
+
+    /// Decode legacy-encoded text lossily instead of treating it as binary.
+    ///
+    /// Files that are not UTF-8 are transcoded from the encoding announced by a
+    /// UTF-16 byte order mark or detected from their content. With this flag,
+    /// malformed sequences are replaced with U+FFFD rather than giving up.
+    #[structopt(long)]
+    pub lossy: bool,
+
+    /// Truncate text files larger than this many bytes.
+    ///
+    /// Oversized files are streamed and cut to their first `--head-lines` and
+    /// last `--tail-lines` lines around a marker such as `… 48,213 lines omitted …`.
+    /// Binary files over the limit are written as a placeholder.
+    #[structopt(long)]
+    pub max_file_bytes: Option<u64>,
+
+    /// Truncate text files with more than this many lines.
+    #[structopt(long)]
+    pub max_file_lines: Option<usize>,
+
+    /// The number of lines kept from the start of a truncated file.
+    #[structopt(long, default_value = "50")]
+    pub head_lines: usize,
+
+    /// The number of lines kept from the end of a truncated file.
+    #[structopt(long, default_value = "20")]
+    pub tail_lines: usize,
+
+    /// Prefix every line with its line number and record each file's line count in its header.
+    ///
+    /// Numbers are right-aligned to the width of the file's last line number,
+    /// and the tail of a truncated file keeps its original numbers. `html`
+    /// output is already numbered, and `shar`, `patch` and archives are not.
+    #[structopt(long)]
+    pub line_numbers: bool,
+
+    /// Remove comments and docstrings from text files.
+    ///
+    /// Files are tokenized with syntect and everything in a `comment` scope is
+    /// dropped, along with lines left empty by it. `shar`, `patch` and archives
+    /// keep files intact.
+    #[structopt(long)]
+    pub strip_comments: bool,
+
+    /// Keep doc comments and docstrings when stripping comments.
+    #[structopt(long, requires = "strip-comments")]
+    pub keep_doc_comments: bool,
+
+    /// Keep a copyright or license comment at the top of each file when stripping comments.
+    #[structopt(long, requires = "strip-comments")]
+    pub keep_license: bool,
+
+    /// Reduce text files to an outline of their declarations.
+    ///
+    /// Function signatures are kept with their bodies collapsed to `{ … }`, and
+    /// types, traits, impls and modules keep their headers and nested
+    /// declarations, all in source order. Declarations are found from syntect's
+    /// `entity.name.*` scopes, so files without a known grammar become empty.
+    #[structopt(long)]
+    pub outline: bool,
+
+    /// Keep up to this many lines of each Jupyter notebook code cell's output.
+    ///
+    /// `.ipynb` files are rendered as Markdown, with markdown cells as text and
+    /// code cells as fenced blocks in the kernel's language. Outputs are dropped
+    /// unless this is set.
+    #[structopt(long, value_name = "lines")]
+    pub notebook_outputs: Option<usize>,
+
+    /// Replace large CSV, TSV, JSON, YAML and TOML files with a summary.
+    ///
+    /// CSV and TSV files keep their header, the inferred type of each column
+    /// and their first rows. JSON, YAML and TOML files are reduced to an
+    /// inferred schema listing each path with its type and sample values.
+    #[structopt(long)]
+    pub summarize_data: bool,
+
+    /// Only summarize data files larger than this many bytes.
+    #[structopt(long, value_name = "bytes", default_value = "65536")]
+    pub summary_min_bytes: u64,
+
+    /// The number of leading CSV and TSV rows kept in a summary.
+    #[structopt(long, value_name = "rows", default_value = "5")]
+    pub summary_rows: usize,
+
+    /// The number of sample values shown per column or path in a summary.
+    #[structopt(long, value_name = "count", default_value = "3")]
+    pub summary_samples: usize,
+
+    /// Write up to this many rows of each table when dumping a SQLite database.
+    ///
+    /// SQLite databases, recognized by their header rather than their
+    /// extension, are written as their `CREATE` statements with a row count
+    /// per table instead of a binary placeholder.
+    #[structopt(long, value_name = "rows", default_value = "0")]
+    pub sqlite_rows: usize,
+
+    /// Write detected secrets as is instead of replacing them with `[REDACTED:kind]`.
+    ///
+    /// Private keys, AWS, GitHub and Slack tokens and random-looking values
+    /// assigned to names like `API_KEY` or `DB_PASSWORD` are redacted by default.
+    /// Found secrets are listed on stderr either way. Binary files written as
+    /// base64 or a hex dump are not scanned.
+    #[structopt(long)]
+    pub keep_secrets: bool,
+
+    /// Print why each path is selected or skipped, after writing the output.
+    ///
+    /// Every file is listed with the rule that decided it: an `--include` or
+    /// `--exclude` pattern, a line of an ignore file, or the hidden-file check.
+    /// Skipped directories are listed once, without their contents.
+    #[structopt(long)]
+    pub explain: bool,
+
+    /// Exit with status 1 if any secret is found, after writing the output.
+    #[structopt(long)]
+    pub fail_on_secret: bool,
+
+    /// An optional subcommand; when omitted, `directory` is flattened.
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands that run instead of flattening a directory.
+#[derive(StructOpt)]
+pub enum Command {
+    /// Explain why paths are selected or skipped by the selection options.
+    ///
+    /// Reports the deciding `--include` or `--exclude` rule, ignore file and
+    /// line, or hidden-file check for each path, using the directory, rules,
+    /// `--allow-hidden` and ignore-file switches given before the subcommand.
+    Explain {
+        /// The paths to explain, relative to the flattened directory.
+        #[structopt(parse(from_os_str), required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Restore the files of a flattened Markdown document into a directory.
+    ///
+    /// Entries with absolute paths or `..` components are refused, and entries
+    /// written as `<non-UTF-8 data>`, converted, e.g. to an outline, or
+    /// truncated are reported because they cannot be restored.
+    Unflatten {
+        /// The flattened file to read.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// The directory under which files are recreated.
+        #[structopt(parse(from_os_str), short, long, default_value = ".")]
+        target: PathBuf,
+
+        /// Print what would be written without touching the filesystem.
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Replace files that already exist.
+        #[structopt(long, conflicts_with = "skip-existing")]
+        overwrite: bool,
+
+        /// Leave files that already exist untouched.
+        #[structopt(long)]
+        skip_existing: bool,
+    },
 }
@@ -0,0 +1,274 @@
+use std::io;
+use std::ops::Range;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use crate::types::CommentOptions;
+
+/// Words that mark the leading comment block of a file as a license header.
+const LICENSE_MARKERS: &[&str] = &["copyright", "license", "licence", "spdx-license-identifier"];
+
+/// A line split into the stretches that are and are not comments.
+struct ClassifiedLine<'a> {
+    /// The line without its line ending.
+    body: &'a str,
+    /// The line ending, `\n`, `\r\n` or empty for a final unterminated line.
+    ending: &'a str,
+    /// Comment stretches of `body`, with whether each one is a doc comment.
+    comments: Vec<(Range<usize>, bool)>,
+}
+
+impl ClassifiedLine<'_> {
+    /// Returns `true` if the line holds a comment and nothing else but whitespace.
+    fn is_comment_only(&self) -> bool {
+        !self.comments.is_empty() && self.code().all(|segment| segment.trim().is_empty())
+    }
+
+    /// Returns the stretches of the body that are not comments.
+    fn code(&self) -> impl Iterator<Item = &str> {
+        let mut start = 0;
+        let mut segments = Vec::new();
+        for (range, _) in &self.comments {
+            segments.push(&self.body[start..range.start]);
+            start = range.end;
+        }
+        segments.push(&self.body[start..]);
+        segments.into_iter()
+    }
+
+    /// Returns the text of all comment stretches, for license detection.
+    fn comment_text(&self) -> String {
+        self.comments.iter().map(|(range, _)| &self.body[range.clone()]).collect()
+    }
+}
+
+/// Removes comments from `text` using the `comment.*` scopes syntect assigns
+/// while tokenizing it as `syntax`, so every language syntect ships is covered
+/// without per-language rules.
+///
+/// Lines left holding only whitespace after their comments are removed are
+/// dropped, and whitespace left before a removed trailing comment is trimmed.
+/// A leading `#!` line is always kept. With `options.keep_doc`, doc comments
+/// are kept: those syntect scopes as `comment.*.documentation` and block
+/// comments opened with `/**`. With `options.keep_license`, the comment block
+/// at the top of the file is kept if it mentions a copyright or license.
+///
+/// Lines whose number in `numbers` is `None`, such as the elision marker of a
+/// truncated file, are kept untouched and separate independently tokenized
+/// stretches of text.
+///
+/// # Arguments
+///
+/// * `text` - The content to strip.
+/// * `numbers` - The original line number of every line of `text`.
+/// * `syntax` - The syntax to tokenize `text` with.
+/// * `syntax_set` - The syntax set `syntax` belongs to.
+/// * `options` - Which comments to keep.
+///
+/// # Returns
+///
+/// The stripped text and the original line numbers of its remaining lines.
+///
+/// # Errors
+///
+/// Returns an error if syntect fails to tokenize a line.
+pub fn strip_comments(
+    text: &str,
+    numbers: &[Option<usize>],
+    syntax: &SyntaxReference,
+    syntax_set: &SyntaxSet,
+    options: &CommentOptions,
+) -> io::Result<(String, Vec<Option<usize>>)> {
+    let lines: Vec<&str> = LinesWithEndings::from(text).collect();
+    let mut stripped = String::with_capacity(text.len());
+    let mut kept_numbers = Vec::with_capacity(numbers.len());
+
+    // Markers split the text into stretches that are tokenized from a fresh state
+    let mut start = 0;
+    while start < lines.len() {
+        if numbers.get(start).copied().flatten().is_none() {
+            stripped.push_str(lines[start]);
+            kept_numbers.push(None);
+            start += 1;
+            continue;
+        }
+        let end = (start..lines.len()).find(|&i| numbers.get(i).copied().flatten().is_none()).unwrap_or(lines.len());
+        let classified = classify(&lines[start..end], syntax, syntax_set)?;
+        let protected = if options.keep_license && start == 0 { license_header_len(&classified) } else { 0 };
+
+        for (offset, line) in classified.iter().enumerate() {
+            let number = numbers.get(start + offset).copied().flatten();
+            let is_shebang = start + offset == 0 && line.body.starts_with("#!");
+            if offset < protected || is_shebang || line.comments.is_empty() {
+                push_line(&mut stripped, line.body, line.ending);
+                kept_numbers.push(number);
+                continue;
+            }
+
+            let mut kept = String::with_capacity(line.body.len());
+            let mut position = 0;
+            for (range, doc) in &line.comments {
+                kept.push_str(&line.body[position..range.start]);
+                if *doc && options.keep_doc {
+                    kept.push_str(&line.body[range.clone()]);
+                }
+                position = range.end;
+            }
+            kept.push_str(&line.body[position..]);
+
+            let kept = kept.trim_end();
+            if !kept.trim_start().is_empty() {
+                push_line(&mut stripped, kept, line.ending);
+                kept_numbers.push(number);
+            }
+        }
+        start = end;
+    }
+
+    Ok((stripped, kept_numbers))
+}
+
+/// Appends a line and its ending to `text`.
+fn push_line(text: &mut String, body: &str, ending: &str) {
+    text.push_str(body);
+    text.push_str(ending);
+}
+
+/// Tokenizes `lines` and records the comment stretches of each one.
+fn classify<'a>(lines: &[&'a str], syntax: &SyntaxReference, syntax_set: &SyntaxSet) -> io::Result<Vec<ClassifiedLine<'a>>> {
+    let comment = Scope::new("comment").map_err(io::Error::other)?;
+    let line_doc = Scope::new("comment.line.documentation").map_err(io::Error::other)?;
+    let block_doc = Scope::new("comment.block.documentation").map_err(io::Error::other)?;
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    // Whether the comment currently open was written as a `/**` doc block
+    let mut doc_block = false;
+    let mut classified = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        let body_len = line.trim_end_matches(['\n', '\r']).len();
+        let ops = state.parse_line(line, syntax_set).map_err(io::Error::other)?;
+        let mut comments: Vec<(Range<usize>, bool)> = Vec::new();
+        let mut position = 0;
+
+        for (offset, op) in ops.iter().map(|(offset, op)| (*offset, Some(op))).chain([(line.len(), None)]) {
+            let in_comment = stack.as_slice().iter().any(|scope| comment.is_prefix_of(*scope));
+            let segment_end = offset.min(body_len);
+            if in_comment && position < segment_end {
+                let doc = doc_block
+                    || stack.as_slice().iter().any(|scope| line_doc.is_prefix_of(*scope) || block_doc.is_prefix_of(*scope));
+                // Merge with the previous stretch when they touch
+                match comments.last_mut() {
+                    Some((range, previous_doc)) if range.end == position && *previous_doc == doc => range.end = segment_end,
+                    _ => comments.push((position..segment_end, doc)),
+                }
+            }
+            position = position.max(segment_end);
+
+            if let Some(op) = op {
+                stack.apply(op).map_err(io::Error::other)?;
+                let now_in_comment = stack.as_slice().iter().any(|scope| comment.is_prefix_of(*scope));
+                if !in_comment && now_in_comment {
+                    let rest = &line[offset..];
+                    doc_block = rest.starts_with("/**") && !rest.starts_with("/**/");
+                } else if !now_in_comment {
+                    doc_block = false;
+                }
+            }
+        }
+
+        classified.push(ClassifiedLine { body: &line[..body_len], ending: &line[body_len..], comments });
+    }
+
+    Ok(classified)
+}
+
+/// Returns the number of leading lines that form a license header, or 0 if
+/// the file does not start with one.
+///
+/// The header is the first run of comment-only lines at the top of the file,
+/// after any `#!` line and blank lines, and counts only if it mentions a
+/// copyright or license.
+fn license_header_len(lines: &[ClassifiedLine]) -> usize {
+    let mut text = String::new();
+    let mut len = 0;
+    for (index, line) in lines.iter().enumerate() {
+        if index == 0 && line.body.starts_with("#!") {
+            continue;
+        }
+        if line.is_comment_only() {
+            text.push_str(&line.comment_text().to_lowercase());
+            len = index + 1;
+        } else if len > 0 || !line.body.trim().is_empty() {
+            // A blank line ends the header once it has started
+            break;
+        }
+    }
+    if LICENSE_MARKERS.iter().any(|marker| text.contains(marker)) { len } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strips `text` as a file with `extension`, numbering its lines from 1.
+    fn strip(text: &str, extension: &str, options: CommentOptions) -> (String, Vec<Option<usize>>) {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let syntax = ss.find_syntax_by_extension(extension).unwrap();
+        let numbers: Vec<Option<usize>> = (1..=text.lines().count()).map(Some).collect();
+        strip_comments(text, &numbers, syntax, &ss, &options).unwrap()
+    }
+
+    #[test]
+    fn test_strip_comments_rust() {
+        let source = "//! Crate docs.\n/// Item docs.\n// Plain.\nfn main() { /* inline */ let x = 1; // trailing\n}\n";
+        let options = CommentOptions { strip: true, ..CommentOptions::default() };
+        let (stripped, numbers) = strip(source, "rs", options);
+        assert_eq!(stripped, "fn main() {  let x = 1;\n}\n");
+        assert_eq!(numbers, vec![Some(4), Some(5)]);
+
+        let options = CommentOptions { strip: true, keep_doc: true, ..CommentOptions::default() };
+        let (stripped, _) = strip(source, "rs", options);
+        assert_eq!(stripped, "//! Crate docs.\n/// Item docs.\nfn main() {  let x = 1;\n}\n");
+    }
+
+    #[test]
+    fn test_strip_comments_multiline_and_doc_blocks() {
+        let source = "/**\n * Javadoc.\n */\nclass A {\n    /* block\n       comment */\n    int x;\n}\n";
+        let options = CommentOptions { strip: true, ..CommentOptions::default() };
+        assert_eq!(strip(source, "java", options).0, "class A {\n    int x;\n}\n");
+
+        let options = CommentOptions { strip: true, keep_doc: true, ..CommentOptions::default() };
+        assert_eq!(strip(source, "java", options).0, "/**\n * Javadoc.\n */\nclass A {\n    int x;\n}\n");
+    }
+
+    #[test]
+    fn test_strip_comments_python_keeps_shebang_and_strings() {
+        let source = "#!/usr/bin/env python\n\"\"\"Module docs.\"\"\"\n# comment\nprint(\"# not a comment\")  # trailing\n";
+        let options = CommentOptions { strip: true, ..CommentOptions::default() };
+        assert_eq!(strip(source, "py", options).0, "#!/usr/bin/env python\nprint(\"# not a comment\")\n");
+    }
+
+    #[test]
+    fn test_strip_comments_license_header() {
+        let source = "// Copyright 2024 Example Corp.\n// SPDX-License-Identifier: MIT\n\n// Helper.\nint x;\n";
+        let options = CommentOptions { strip: true, keep_license: true, ..CommentOptions::default() };
+        assert_eq!(strip(source, "c", options).0, "// Copyright 2024 Example Corp.\n// SPDX-License-Identifier: MIT\n\nint x;\n");
+
+        // A leading comment without license wording is stripped like any other
+        let source = "// Helper.\nint x;\n";
+        assert_eq!(strip(source, "c", options).0, "int x;\n");
+    }
+
+    #[test]
+    fn test_strip_comments_keeps_markers() {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let syntax = ss.find_syntax_by_extension("rs").unwrap();
+        let text = "// a\nlet a = 1;\n… 5 lines omitted …\n// z\nlet z = 2;\n";
+        let numbers = vec![Some(1), Some(2), None, Some(8), Some(9)];
+        let options = CommentOptions { strip: true, ..CommentOptions::default() };
+        let (stripped, kept) = strip_comments(text, &numbers, syntax, &ss, &options).unwrap();
+        assert_eq!(stripped, "let a = 1;\n… 5 lines omitted …\nlet z = 2;\n");
+        assert_eq!(kept, vec![Some(2), None, Some(9)]);
+    }
+}
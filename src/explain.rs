@@ -0,0 +1,588 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use ignore::gitignore::{Gitignore, GitignoreBuilder, Glob};
+use ignore::Match;
+use walkdir::WalkDir;
+use crate::file_processing::{self, FLATTEN_IGNORE_FILE};
+use crate::rules::{self, Rule};
+use crate::types::{ExcludeList, IgnoreOptions, IncludeList, ProcessOptions};
+
+/// The ignore files read in every directory, highest precedence first, with
+/// whether they only apply inside a git repository.
+const IGNORE_FILES: &[(&str, bool)] = &[(FLATTEN_IGNORE_FILE, false), (".ignore", false), (".gitignore", true)];
+
+/// Why a path is selected or skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// No rule, ignore file or hidden-file check applies, so the path is selected.
+    Unmatched,
+    /// An `--include` or `--exclude` rule, or the built-in `flatten` exclude, decided.
+    Rule {
+        /// Whether the rule is an include.
+        include: bool,
+        /// The pattern as it was written.
+        pattern: String,
+        /// The kind of pattern, see `Rule::kind`.
+        kind: &'static str,
+        /// Whether the rule is the built-in `flatten` exclude.
+        builtin: bool,
+    },
+    /// Include rules were given and none of them matches.
+    NotIncluded,
+    /// The path, or the directory named here, is hidden and `--allow-hidden` is not set.
+    Hidden(PathBuf),
+    /// A line of an ignore file matched the path or the directory named by `matched`.
+    IgnoreFile {
+        /// The ignore file, relative to the flattened directory if inside it.
+        file: PathBuf,
+        /// The 1-based line of the pattern, if it could be found in the file.
+        line: Option<usize>,
+        /// The pattern as written in the file.
+        pattern: String,
+        /// The path the pattern matched: the explained path or one of its parents.
+        matched: PathBuf,
+        /// Whether the pattern is a `!` negation that re-includes the path.
+        whitelist: bool,
+    },
+    /// The path is a symbolic link, which the walker does not follow.
+    Symlink,
+    /// The walker does not reach the path for a reason none of the others describes.
+    Unreached,
+    /// A git selection mode is set and git reports no change to the file.
+    Unchanged,
+    /// The path is the output file being written.
+    OutputFile,
+}
+
+/// The verdict on one path with the rule that decided it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The path relative to the flattened directory.
+    pub path: PathBuf,
+    /// Whether the path is a directory.
+    pub is_dir: bool,
+    /// Whether the path is selected.
+    pub selected: bool,
+    /// The deciding rule.
+    pub reason: Reason,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}: ", self.path.display(), if self.is_dir { "/" } else { "" })?;
+        let verdict = if self.selected { "selected" } else { "skipped" };
+        match &self.reason {
+            Reason::Unmatched => write!(f, "{}, no rule or ignore file applies", verdict),
+            Reason::Rule { builtin: true, pattern, .. } => write!(f, "{} by the built-in exclude '{}'", verdict, pattern),
+            Reason::Rule { include, pattern, kind, .. } => {
+                let flag = if *include { "--include" } else { "--exclude" };
+                write!(f, "{} by {} {} '{}'", verdict, flag, kind, pattern)
+            }
+            Reason::NotIncluded => write!(f, "{}, no --include rule matches", verdict),
+            Reason::Hidden(hidden) if hidden == &self.path => write!(f, "{}, hidden (see --allow-hidden)", verdict),
+            Reason::Hidden(hidden) => write!(f, "{}, {}/ is hidden (see --allow-hidden)", verdict, hidden.display()),
+            Reason::IgnoreFile { file, line, pattern, matched, .. } => {
+                write!(f, "{} by {}", verdict, file.display())?;
+                if let Some(line) = line {
+                    write!(f, ":{}", line)?;
+                }
+                write!(f, " '{}'", pattern)?;
+                if matched != &self.path {
+                    write!(f, " matching {}/", matched.display())?;
+                }
+                Ok(())
+            }
+            Reason::Symlink => write!(f, "{}, symbolic links are not followed", verdict),
+            Reason::Unreached => write!(f, "{}, the walk does not reach it", verdict),
+            Reason::Unchanged => write!(f, "{}, git reports no change selected by --changed-since, --staged or --untracked", verdict),
+            Reason::OutputFile => write!(f, "{}, it is the output file", verdict),
+        }
+    }
+}
+
+/// The ignore files of one directory.
+struct DirIgnores {
+    /// Whether the directory contains `.git`, i.e. is a repository root.
+    has_git: bool,
+    /// The matchers for `IGNORE_FILES`, in the same order, empty where the
+    /// kind of file is switched off.
+    files: Vec<Gitignore>,
+    /// The matcher for `.git/info/exclude`.
+    exclude: Gitignore,
+}
+
+/// Explains why paths are selected or skipped.
+///
+/// Whether a path is selected is taken from the walk `process_files_with_options`
+/// makes: the `--include` and `--exclude` rules decide first, then a path the
+/// walker does not reach is skipped and one it reaches is selected, unless a
+/// git changes are given in the options and do not list it. The walk is run
+/// once, on first use, and its result cached.
+///
+/// The reason a path is skipped by the walk is found by matching the first
+/// component the walker did not reach against the `.flattenignore`, `.ignore`,
+/// `.gitignore`, `.git/info/exclude` and global git ignore files, then against
+/// the hidden-file setting. Ignore files are read once per directory and cached.
+pub struct Explainer<'a> {
+    directory: &'a Path,
+    exclude: &'a ExcludeList,
+    include: &'a IncludeList,
+    options: ProcessOptions,
+    walked: Option<HashSet<PathBuf>>,
+    global: Gitignore,
+    dirs: HashMap<PathBuf, DirIgnores>,
+}
+
+impl<'a> Explainer<'a> {
+    /// Creates an explainer for the walk `process_files_with_options` would make.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The canonical directory being flattened.
+    /// * `exclude` - The exclude rules.
+    /// * `include` - The include rules.
+    /// * `options` - The options selecting hidden files and ignore files.
+    pub fn new(directory: &'a Path, exclude: &'a ExcludeList, include: &'a IncludeList, options: &ProcessOptions) -> Self {
+        let global = if options.ignore.global_gitignore { Gitignore::global().0 } else { Gitignore::empty() };
+        Explainer {
+            directory,
+            exclude,
+            include,
+            options: options.clone(),
+            walked: None,
+            global,
+            dirs: HashMap::new(),
+        }
+    }
+
+    /// Explains whether a path is selected and why.
+    ///
+    /// A directory is reported as selected if the walk enters it, even though
+    /// only files are written.
+    ///
+    /// # Arguments
+    ///
+    /// * `rel_path` - The path relative to the flattened directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path does not exist or the walk fails.
+    pub fn explain(&mut self, rel_path: &Path) -> io::Result<Explanation> {
+        let metadata = fs::symlink_metadata(self.directory.join(rel_path))?;
+        let is_dir = metadata.is_dir();
+        let explanation = |selected, reason| Explanation { path: rel_path.to_path_buf(), is_dir, selected, reason };
+
+        // Directories are only left unwalked when no later include could select anything inside
+        let decision = rules::decide(rel_path, self.exclude, self.include);
+        let skipped_by_rules = if is_dir { rules::skips_directory(rel_path, self.exclude, self.include) } else { !decision.included };
+        if skipped_by_rules {
+            return Ok(explanation(false, decision.rule.map_or(Reason::NotIncluded, |rule| rule_reason(rule, false))));
+        }
+        if metadata.file_type().is_symlink() {
+            return Ok(explanation(false, Reason::Symlink));
+        }
+
+        if !self.is_walked(rel_path)? {
+            return Ok(explanation(false, self.walk_stop(rel_path, is_dir)));
+        }
+        if !is_dir && !self.is_changed(rel_path) {
+            return Ok(explanation(false, Reason::Unchanged));
+        }
+
+        // A directory may be entered despite an exclude, for the sake of a later include
+        let reason = match decision.rule.filter(|_| decision.included) {
+            Some(rule) => rule_reason(rule, true),
+            None => self
+                .ignore_match(rel_path, is_dir)
+                .filter(|reason| matches!(reason, Reason::IgnoreFile { whitelist: true, .. }))
+                .unwrap_or(Reason::Unmatched),
+        };
+        Ok(explanation(true, reason))
+    }
+
+    /// Returns `true` if the walker reaches `rel_path`, walking the directory on first use.
+    fn is_walked(&mut self, rel_path: &Path) -> io::Result<bool> {
+        if self.walked.is_none() {
+            self.walked = Some(file_processing::walked_paths(self.directory, self.exclude, self.include, &self.options)?);
+        }
+        Ok(self.walked.as_ref().is_some_and(|paths| paths.contains(rel_path)))
+    }
+
+    /// Returns `true` if no git changes are given or they include `rel_path`.
+    fn is_changed(&self, rel_path: &Path) -> bool {
+        self.options.git.as_ref().is_none_or(|changes| changes.paths.contains(rel_path))
+    }
+
+    /// Finds why the walk stops before reaching `rel_path`, from the first of
+    /// its components the walker did not reach.
+    fn walk_stop(&mut self, rel_path: &Path, is_dir: bool) -> Reason {
+        let components: Vec<&Path> = rel_path.ancestors().filter(|p| !p.as_os_str().is_empty()).collect();
+        for component in components.into_iter().rev() {
+            if self.walked.as_ref().is_some_and(|paths| paths.contains(component)) {
+                continue;
+            }
+            let component_is_dir = component != rel_path || is_dir;
+            if component_is_dir && rules::skips_directory(component, self.exclude, self.include) {
+                let decision = rules::decide(component, self.exclude, self.include);
+                return decision.rule.map_or(Reason::NotIncluded, |rule| rule_reason(rule, false));
+            }
+            if let Some(reason @ Reason::IgnoreFile { whitelist: false, .. }) = self.ignore_match(component, component_is_dir) {
+                return reason;
+            }
+            let hidden = component.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden && !self.options.allow_hidden {
+                return Reason::Hidden(component.to_path_buf());
+            }
+            break;
+        }
+        Reason::Unreached
+    }
+
+    /// Returns the ignore-file pattern deciding `rel_path`, if any.
+    ///
+    /// Every ignore file is asked with `matched_path_or_any_parents`, so the
+    /// `ignore` crate itself decides which glob of the file applies to the path
+    /// or one of its parents. The walker ranks `.flattenignore` over `.ignore`
+    /// over `.gitignore` files, deeper files of a kind over shallower ones,
+    /// then `.git/info/exclude` and the global git ignore file; the first
+    /// match in that order is reported.
+    fn ignore_match(&mut self, rel_path: &Path, is_dir: bool) -> Option<Reason> {
+        let path = self.directory.join(rel_path);
+        let parents: Vec<PathBuf> = path.ancestors().skip(1).map(Path::to_path_buf).collect();
+        for parent in &parents {
+            if !self.dirs.contains_key(parent) {
+                let ignores = read_dir_ignores(parent, &self.options.ignore);
+                self.dirs.insert(parent.clone(), ignores);
+            }
+        }
+
+        // Directories above the walk root are still searched for `.git`, but
+        // their ignore files only apply if parents are read
+        let ignore = self.options.ignore;
+        let any_git = parents.iter().any(|parent| self.dirs[parent].has_git);
+        let mut file_matches: Vec<Option<(&Gitignore, &Glob)>> = vec![None; IGNORE_FILES.len()];
+        let mut exclude_match = None;
+        let mut saw_git = false;
+        // Deeper directories take precedence, and git ignore files above the repository root do not apply
+        for parent in parents.iter().filter(|parent| ignore.parents || parent.starts_with(self.directory)) {
+            let ignores = &self.dirs[parent];
+            for (i, (_, needs_git)) in IGNORE_FILES.iter().enumerate() {
+                if file_matches[i].is_none() && (!needs_git || (any_git && !saw_git)) {
+                    file_matches[i] = matched_glob(&ignores.files[i], &path, is_dir);
+                }
+            }
+            if exclude_match.is_none() && any_git && !saw_git {
+                exclude_match = matched_glob(&ignores.exclude, &path, is_dir);
+            }
+            saw_git = saw_git || ignores.has_git;
+        }
+        let global_match = if any_git { matched_glob(&self.global, &path, is_dir) } else { None };
+
+        let (matcher, glob) = file_matches.into_iter().flatten().next().or(exclude_match).or(global_match)?;
+        // The glob may have matched a parent rather than the path itself, which is checked first
+        let matched = rel_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .find(|&ancestor| {
+                let ancestor_is_dir = ancestor != rel_path || is_dir;
+                matches!(matcher.matched(self.directory.join(ancestor), ancestor_is_dir), Match::Ignore(g) | Match::Whitelist(g) if std::ptr::eq(g, glob))
+            })
+            .unwrap_or(rel_path);
+        let file = glob.from().map(Path::to_path_buf).unwrap_or_default();
+        Some(Reason::IgnoreFile {
+            line: glob_line(glob),
+            file: file.strip_prefix(self.directory).map(Path::to_path_buf).unwrap_or(file),
+            pattern: glob.original().to_string(),
+            matched: matched.to_path_buf(),
+            whitelist: glob.is_whitelist(),
+        })
+    }
+}
+
+/// Explains every path under `directory`, in walk order.
+///
+/// Files are listed with their verdict. Directories are listed only when
+/// skipped, and their contents are then left out of the report.
+///
+/// # Arguments
+///
+/// * `directory` - The canonical directory being flattened.
+/// * `output_file` - The output file, reported as skipped if it is inside `directory`.
+/// * `exclude` - The exclude rules.
+/// * `include` - The include rules.
+/// * `options` - The options selecting hidden files and ignore files.
+///
+/// # Errors
+///
+/// Returns an error if an entry cannot be read.
+pub fn explain_tree(
+    directory: &Path,
+    output_file: Option<&Path>,
+    exclude: &ExcludeList,
+    include: &IncludeList,
+    options: &ProcessOptions,
+) -> io::Result<Vec<Explanation>> {
+    let output_path = output_file.and_then(|path| path.canonicalize().ok());
+    let mut explainer = Explainer::new(directory, exclude, include, options);
+    let mut explanations = Vec::new();
+
+    let mut walker = WalkDir::new(directory).min_depth(1).sort_by_file_name().into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry.map_err(io::Error::other)?;
+        let rel_path = entry.path().strip_prefix(directory).unwrap_or(entry.path());
+        let mut explanation = explainer.explain(rel_path)?;
+        if explanation.is_dir {
+            if !explanation.selected {
+                walker.skip_current_dir();
+                explanations.push(explanation);
+            }
+            continue;
+        }
+        if explanation.selected && output_path.as_deref() == Some(entry.path()) {
+            explanation = Explanation { selected: false, reason: Reason::OutputFile, ..explanation };
+        }
+        explanations.push(explanation);
+    }
+    Ok(explanations)
+}
+
+/// Resolves a path given to `explain`, relative to the flattened directory
+/// unless it is absolute.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if the path lies outside `directory`.
+pub fn relative_path(directory: &Path, path: &Path) -> io::Result<PathBuf> {
+    rules::relative_to(directory, path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is outside {}", path.display(), directory.display())))
+}
+
+/// Describes an include or exclude rule as a `Reason`.
+fn rule_reason(rule: &Rule, include: bool) -> Reason {
+    Reason::Rule { include, pattern: rule.pattern.clone(), kind: rule.kind(), builtin: rule.is_builtin() }
+}
+
+/// Reads the ignore files of one directory that `options` switches on.
+///
+/// Like the walker, every matcher is rooted at `dir`, including the one for
+/// `.git/info/exclude`.
+fn read_dir_ignores(dir: &Path, options: &IgnoreOptions) -> DirIgnores {
+    let read = |enabled: bool, path: PathBuf| {
+        if !enabled || !path.is_file() {
+            return Gitignore::empty();
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        builder.add(path);
+        builder.build().unwrap_or_else(|_| Gitignore::empty())
+    };
+    let enabled = [options.flattenignore, options.dot_ignore, options.gitignore];
+    DirIgnores {
+        // Like the walker, repositories are only looked for when git's own ignore files are read
+        has_git: (options.gitignore || options.git_exclude) && dir.join(".git").exists(),
+        files: IGNORE_FILES.iter().zip(enabled).map(|((name, _), enabled)| read(enabled, dir.join(name))).collect(),
+        exclude: read(options.git_exclude, dir.join(".git").join("info").join("exclude")),
+    }
+}
+
+/// Returns the matcher and glob of an ignore or whitelist match of `path` or
+/// one of its parents under the matcher's root.
+fn matched_glob<'g>(matcher: &'g Gitignore, path: &Path, is_dir: bool) -> Option<(&'g Gitignore, &'g Glob)> {
+    match matcher.matched_path_or_any_parents(path, is_dir) {
+        Match::Ignore(glob) | Match::Whitelist(glob) => Some((matcher, glob)),
+        Match::None => None,
+    }
+}
+
+/// Finds the 1-based line of the ignore file that produced `glob`.
+///
+/// The last matching glob of a file decides, and a later line with the same
+/// text would match just the same, so the glob is always the last line of
+/// its file with its text.
+fn glob_line(glob: &Glob) -> Option<usize> {
+    let content = fs::read_to_string(glob.from()?).ok()?;
+    content.lines().enumerate().filter(|(_, line)| line.trim_end() == glob.original()).map(|(i, _)| i + 1).last()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_explain_reasons() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(dir_path.join("src")).unwrap();
+        fs::create_dir_all(dir_path.join(".config")).unwrap();
+        fs::write(dir_path.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir_path.join("src").join("debug.log"), "log\n").unwrap();
+        fs::write(dir_path.join("src").join("keep.log"), "log\n").unwrap();
+        fs::write(dir_path.join("Cargo.lock"), "lock\n").unwrap();
+        fs::write(dir_path.join(".config").join("app.toml"), "a = 1\n").unwrap();
+        fs::write(dir_path.join(".ignore"), "# logs\n*.log\n!keep.log\n").unwrap();
+
+        let exclude = ExcludeList::new(&dir_path, vec![PathBuf::from("*.lock")]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let mut explainer = Explainer::new(&dir_path, &exclude, &include, &ProcessOptions::default());
+        let explain = |explainer: &mut Explainer, path: &str| explainer.explain(Path::new(path)).unwrap().to_string();
+
+        assert_eq!(explain(&mut explainer, "src/main.rs"), "src/main.rs: selected, no rule or ignore file applies");
+        assert_eq!(explain(&mut explainer, "src/debug.log"), "src/debug.log: skipped by .ignore:2 '*.log'");
+        assert_eq!(explain(&mut explainer, "src/keep.log"), "src/keep.log: selected by .ignore:3 '!keep.log'");
+        assert_eq!(explain(&mut explainer, "Cargo.lock"), "Cargo.lock: skipped by --exclude glob '*.lock'");
+        assert_eq!(explain(&mut explainer, ".config/app.toml"), ".config/app.toml: skipped, .config/ is hidden (see --allow-hidden)");
+        assert_eq!(explain(&mut explainer, ".config"), ".config/: skipped, hidden (see --allow-hidden)");
+        assert!(explainer.explain(Path::new("missing.rs")).is_err());
+
+        // A literal include reaches a hidden path, and other paths are no longer selected
+        let include = IncludeList::new(&dir_path, vec![PathBuf::from(".config/app.toml")]);
+        let mut explainer = Explainer::new(&dir_path, &exclude, &include, &ProcessOptions::default());
+        assert_eq!(explain(&mut explainer, ".config/app.toml"), ".config/app.toml: selected by --include literal '.config/app.toml'");
+        assert_eq!(explain(&mut explainer, "src/main.rs"), "src/main.rs: skipped, no --include rule matches");
+    }
+
+    #[test]
+    fn test_explain_ignore_file_switches() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::write(dir_path.join("notes.txt"), "notes\n").unwrap();
+        fs::write(dir_path.join(".ignore"), "*.txt\n").unwrap();
+        fs::write(dir_path.join(FLATTEN_IGNORE_FILE), "!notes.txt\n").unwrap();
+
+        let exclude = ExcludeList::new(&dir_path, vec![]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let explain = |options: &ProcessOptions| Explainer::new(&dir_path, &exclude, &include, options).explain(Path::new("notes.txt")).unwrap().to_string();
+
+        // `.flattenignore` overrides `.ignore`
+        assert_eq!(explain(&ProcessOptions::default()), "notes.txt: selected by .flattenignore:1 '!notes.txt'");
+        let ignore = IgnoreOptions { flattenignore: false, ..IgnoreOptions::default() };
+        assert_eq!(explain(&ProcessOptions { ignore, ..ProcessOptions::default() }), "notes.txt: skipped by .ignore:1 '*.txt'");
+        let ignore = IgnoreOptions { flattenignore: false, dot_ignore: false, ..IgnoreOptions::default() };
+        assert_eq!(explain(&ProcessOptions { ignore, ..ProcessOptions::default() }), "notes.txt: selected, no rule or ignore file applies");
+    }
+
+    #[test]
+    fn test_explain_lines_of_repeated_and_negated_patterns() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::write(dir_path.join("debug.log"), "log\n").unwrap();
+        fs::write(dir_path.join("keep.log"), "log\n").unwrap();
+        fs::write(dir_path.join("notes.txt"), "notes\n").unwrap();
+        fs::write(dir_path.join(".ignore"), "*.log\n!keep.log\n!notes.txt\n# again\n*.log\n*.txt\n!notes.txt\n").unwrap();
+
+        let exclude = ExcludeList::new(&dir_path, vec![]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let mut explainer = Explainer::new(&dir_path, &exclude, &include, &ProcessOptions::default());
+        let mut explain = |path: &str| explainer.explain(Path::new(path)).unwrap().to_string();
+
+        // The last matching line decides, not the first line with the same text
+        assert_eq!(explain("debug.log"), "debug.log: skipped by .ignore:5 '*.log'");
+        assert_eq!(explain("keep.log"), "keep.log: skipped by .ignore:5 '*.log'");
+        assert_eq!(explain("notes.txt"), "notes.txt: selected by .ignore:7 '!notes.txt'");
+    }
+
+    #[test]
+    fn test_explain_negation_in_nested_gitignore() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(dir_path.join(".git")).unwrap();
+        fs::create_dir_all(dir_path.join("src")).unwrap();
+        fs::create_dir_all(dir_path.join("logs")).unwrap();
+        fs::write(dir_path.join(".gitignore"), "*.log\nlogs/\n").unwrap();
+        fs::write(dir_path.join("src").join(".gitignore"), "!keep.log\n").unwrap();
+        for file in ["src/main.rs", "src/keep.log", "src/debug.log", "logs/today.txt"] {
+            fs::write(dir_path.join(file), "content\n").unwrap();
+        }
+
+        let exclude = ExcludeList::new(&dir_path, vec![]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let mut explainer = Explainer::new(&dir_path, &exclude, &include, &ProcessOptions::default());
+        let mut explain = |path: &str| explainer.explain(Path::new(path)).unwrap().to_string();
+
+        // The deeper file re-includes what the top-level one ignores
+        assert_eq!(explain("src/keep.log"), "src/keep.log: selected by src/.gitignore:1 '!keep.log'");
+        assert_eq!(explain("src/debug.log"), "src/debug.log: skipped by .gitignore:1 '*.log'");
+        assert_eq!(explain("logs/today.txt"), "logs/today.txt: skipped by .gitignore:2 'logs/' matching logs/");
+
+        let selected: Vec<String> = explain_tree(&dir_path, None, &exclude, &include, &ProcessOptions::default())
+            .unwrap()
+            .iter()
+            .filter(|e| e.selected && !e.is_dir)
+            .map(|e| e.path.display().to_string())
+            .collect();
+        assert_eq!(selected, vec!["src/keep.log", "src/main.rs"]);
+    }
+
+    #[test]
+    fn test_explain_tree_agrees_with_walk() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(dir_path.join("build").join("gen")).unwrap();
+        fs::create_dir_all(dir_path.join("src")).unwrap();
+        fs::write(dir_path.join("build").join("gen").join("out.rs"), "generated\n").unwrap();
+        fs::write(dir_path.join("src").join("lib.rs"), "lib\n").unwrap();
+        fs::write(dir_path.join("src").join(".env"), "env\n").unwrap();
+        fs::write(dir_path.join("README.md"), "readme\n").unwrap();
+        fs::write(dir_path.join(".ignore"), "build/\n!build/gen/out.rs\n").unwrap();
+
+        let exclude = ExcludeList::new(&dir_path, vec![]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let output_dir = tempdir().unwrap();
+        let output_path = output_dir.path().join("output.jsonl");
+        let options = ProcessOptions { format: crate::types::OutputFormat::Jsonl, ..ProcessOptions::default() };
+        crate::file_processing::process_files_with_options(&dir_path, &output_path, &exclude, &include, &options).unwrap();
+        let mut flattened: Vec<String> = fs::read_to_string(&output_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<crate::types::FileRecord>(line).unwrap().path)
+            .collect();
+        flattened.sort();
+
+        // Every file the report selects is exactly a file the walk flattens
+        let explanations = explain_tree(&dir_path, None, &exclude, &include, &options).unwrap();
+        let selected: Vec<String> = explanations
+            .iter()
+            .filter(|e| e.selected && !e.is_dir)
+            .map(|e| e.path.display().to_string())
+            .collect();
+        assert_eq!(selected, flattened);
+        assert!(explanations.iter().any(|e| e.to_string() == "build/: skipped by .ignore:1 'build/'"));
+    }
+
+    #[test]
+    fn test_explain_tree_reports_skipped_directories_once() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(dir_path.join("target").join("debug")).unwrap();
+        fs::write(dir_path.join("target").join("debug").join("app"), "bin").unwrap();
+        fs::write(dir_path.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir_path.join("out.md"), "").unwrap();
+
+        let exclude = ExcludeList::new(&dir_path, vec![PathBuf::from("target")]);
+        let include = IncludeList::new(&dir_path, vec![]);
+        let report: Vec<String> = explain_tree(&dir_path, Some(&dir_path.join("out.md")), &exclude, &include, &ProcessOptions::default())
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            report,
+            vec![
+                "main.rs: selected, no rule or ignore file applies",
+                "out.md: skipped, it is the output file",
+                "target/: skipped by --exclude literal 'target'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let base = Path::new("/project");
+        assert_eq!(relative_path(base, Path::new("./src/../src/main.rs")).unwrap(), PathBuf::from("src/main.rs"));
+        assert_eq!(relative_path(base, Path::new("/project/docs")).unwrap(), PathBuf::from("docs"));
+        assert_eq!(relative_path(base, Path::new("../elsewhere")).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,553 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use crate::html::HtmlFormatter;
+use crate::patch::PatchFormatter;
+use crate::template::{Template, TemplateFormatter};
+use crate::types::{ContentEncoding, FileRecord, OutputFormat, ProcessOptions};
+
+/// The start of the header note naming the encoding that text was transcoded from.
+pub const TRANSCODED_PREFIX: &str = "transcoded from ";
+
+/// The header note marking content reduced to an outline.
+pub const OUTLINE_NOTE: &str = "outline";
+
+/// The header note marking content whose comments were removed.
+pub const COMMENTS_STRIPPED_NOTE: &str = "comments stripped";
+
+/// The header note marking content cut to a head and tail around an elision marker.
+pub const TRUNCATED_NOTE: &str = "truncated";
+
+/// Describes the file currently being written, passed to every per-file hook.
+#[derive(Debug, Clone, Copy)]
+pub struct FileInfo<'a> {
+    /// 1-based position of the file in traversal order.
+    pub index: usize,
+    /// Path relative to the flattened directory.
+    pub path: &'a Path,
+    /// The syntax detected from the file extension.
+    pub syntax: &'a SyntaxReference,
+    /// The syntax set `syntax` belongs to, for formatters that tokenize content.
+    pub syntax_set: &'a SyntaxSet,
+    /// Size of the file on disk in bytes.
+    pub size: u64,
+    /// Number of lines in the decoded text, counting the whole file even when
+    /// the content was truncated, or 0 when the file is binary.
+    pub line_count: usize,
+    /// Last modification time, when the platform reports one.
+    pub modified: Option<SystemTime>,
+    /// Whether any execute bit is set on the file.
+    pub executable: bool,
+    /// Whether the content passed to `write_content` is the decoded file text,
+    /// base64, a hex dump, or the `<non-UTF-8 data>` placeholder.
+    pub encoding: ContentEncoding,
+    /// The guessed MIME type, for binary files only.
+    pub mime: Option<&'a str>,
+    /// Lowercase hex SHA-256 digest of the file, for binary files only.
+    pub sha256: Option<&'a str>,
+    /// The legacy encoding the text was transcoded from, or `None` if the file
+    /// was UTF-8 to begin with.
+    pub charset: Option<&'a str>,
+    /// Whether malformed sequences were replaced with U+FFFD while transcoding.
+    pub lossy: bool,
+    /// Whether the content was cut to a head and tail around an elision marker.
+    pub truncated: bool,
+    /// Whether comments were removed from the content.
+    pub comments_stripped: bool,
+    /// Whether the content is an outline of the file's declarations.
+    pub outline: bool,
+    /// How the content was rendered from the file instead of being its text,
+    /// e.g. `notebook` for a Jupyter notebook rendered as Markdown.
+    pub conversion: Option<&'a str>,
+    /// Whether line numbers were requested, in which case headers record the
+    /// total line count.
+    pub line_numbers: bool,
+}
+
+impl FileInfo<'_> {
+    /// Returns the lowercased syntax name used as the fence label, e.g. `rust`.
+    pub fn syntax_name(&self) -> String {
+        self.syntax.name.to_lowercase()
+    }
+
+    /// Returns the note that follows the path in headers, such as
+    /// `412 lines, transcoded from Shift_JIS`, or `None` if there is nothing to note.
+    ///
+    /// The line count is noted when line numbers were requested, conversions,
+    /// stripped comments, outlines and truncation are named, and the source
+    /// encoding is noted when the text was transcoded.
+    pub fn header_note(&self) -> Option<String> {
+        let mut notes = Vec::new();
+        if self.line_numbers && self.encoding == ContentEncoding::Utf8 {
+            notes.push(match self.line_count {
+                1 => "1 line".to_string(),
+                count => format!("{} lines", count),
+            });
+        }
+        if let Some(conversion) = self.conversion {
+            notes.push(conversion.to_string());
+        }
+        if self.comments_stripped {
+            notes.push(COMMENTS_STRIPPED_NOTE.to_string());
+        }
+        if self.outline {
+            notes.push(OUTLINE_NOTE.to_string());
+        }
+        if self.truncated {
+            notes.push(TRUNCATED_NOTE.to_string());
+        }
+        if let Some(charset) = self.charset {
+            notes.push(format!("{}{}", TRANSCODED_PREFIX, charset));
+            if self.lossy {
+                notes.push("lossy".to_string());
+            }
+        }
+        (!notes.is_empty()).then(|| notes.join(", "))
+    }
+}
+
+/// Controls the layout of the flattened output.
+///
+/// `process_files_with_formatter` calls `begin_document` once, then
+/// `begin_file`, `write_content` and `end_file` for every file in traversal
+/// order, and finally `end_document`. Every hook except `write_content` does
+/// nothing by default, so simple layouts only need to implement one method.
+pub trait OutputFormatter {
+    /// Writes anything that precedes the first file.
+    ///
+    /// `directory` is the directory being flattened.
+    fn begin_document(&mut self, _output: &mut dyn Write, _directory: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes anything that precedes a file's content, such as a header.
+    fn begin_file(&mut self, _output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns `true` if binary files should always be passed to `write_content`
+    /// base64-encoded, whatever `BinaryPolicy` other than `Skip` is selected.
+    ///
+    /// Formatters that must reproduce files byte for byte, such as the shell
+    /// archive, override this.
+    fn encode_binary(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` if the formatter shows line numbers itself, so that
+    /// `--line-numbers` passes it the content without a number gutter.
+    fn numbers_lines(&self) -> bool {
+        false
+    }
+
+    /// Writes a file's content.
+    ///
+    /// `content` is the decoded text when `file.encoding` is `ContentEncoding::Utf8`,
+    /// standard base64 when it is `ContentEncoding::Base64`, an `xxd`-style dump
+    /// when it is `ContentEncoding::Hexdump`, or a one-line `<non-UTF-8 data>`
+    /// placeholder with the size, MIME type and digest when it is
+    /// `ContentEncoding::NonUtf8`.
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()>;
+
+    /// Writes anything that follows a file's content.
+    fn end_file(&mut self, _output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes anything that follows the last file.
+    fn end_document(&mut self, _output: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds the formatter for `options.template`, or else the built-in formatter
+/// selected by `options.format`.
+///
+/// # Errors
+///
+/// Returns an error if the template cannot be read or parsed, an `InvalidInput`
+/// error if HTML output is requested with a theme that syntect does not ship,
+/// or if `options.format` is an archive format, which has no formatter.
+pub fn formatter_for(options: &ProcessOptions) -> io::Result<Box<dyn OutputFormatter>> {
+    // A user-defined template replaces the built-in layouts
+    if let Some(path) = &options.template {
+        return Ok(Box::new(TemplateFormatter::new(Template::from_file(path)?)));
+    }
+
+    Ok(match options.format {
+        OutputFormat::Markdown => Box::new(MarkdownFormatter),
+        OutputFormat::Json => Box::new(JsonFormatter::document()),
+        OutputFormat::Jsonl => Box::new(JsonFormatter::lines()),
+        OutputFormat::Xml => Box::new(XmlFormatter),
+        OutputFormat::Html => Box::new(HtmlFormatter::new(options.theme.as_deref())?),
+        OutputFormat::Shar => Box::new(ShellArchiveFormatter::default()),
+        OutputFormat::Patch => Box::new(PatchFormatter),
+        OutputFormat::Tar | OutputFormat::TarGz | OutputFormat::Zip => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "archive formats are written by `bundle::write_bundle`, not a formatter",
+            ));
+        }
+    })
+}
+
+/// The default layout: a `## path` header followed by a fenced block labelled
+/// with the syntax name, and an empty line between files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownFormatter;
+
+impl OutputFormatter for MarkdownFormatter {
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        match file.header_note() {
+            Some(note) => writeln!(output, "## {} ({})", file.path.display(), note),
+            None => writeln!(output, "## {}", file.path.display()),
+        }
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        // Pick a fence longer than any backtick run in the content so the block cannot close early
+        let fence = fence_for(content);
+        writeln!(output, "{}{}", fence, file.syntax_name())?;
+        writeln!(output, "{}", content)?;
+        writeln!(output, "{}", fence) // Close the syntax block
+    }
+
+    fn end_file(&mut self, output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        writeln!(output) // Add an empty line between files
+    }
+}
+
+/// Writes one `FileRecord` per file, either inside a single JSON document with
+/// a top-level `files` array or as newline-delimited JSON.
+#[derive(Debug, Clone, Default)]
+pub struct JsonFormatter {
+    lines: bool,
+    written: usize,
+    content: Option<String>,
+}
+
+impl JsonFormatter {
+    /// Creates a formatter writing one document with a `files` array.
+    pub fn document() -> Self {
+        JsonFormatter::default()
+    }
+
+    /// Creates a formatter writing one record per line (JSONL).
+    pub fn lines() -> Self {
+        JsonFormatter { lines: true, ..JsonFormatter::default() }
+    }
+}
+
+impl OutputFormatter for JsonFormatter {
+    fn begin_document(&mut self, output: &mut dyn Write, _directory: &Path) -> io::Result<()> {
+        if !self.lines {
+            write!(output, "{{\"files\":[")?;
+        }
+        Ok(())
+    }
+
+    fn write_content(&mut self, _output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        // The record is written in `end_file`, once all content is known
+        if file.encoding != ContentEncoding::NonUtf8 {
+            self.content.get_or_insert_with(String::new).push_str(content);
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        let record = FileRecord {
+            path: file.path.display().to_string(),
+            syntax: file.syntax_name(),
+            size: file.size,
+            encoding: file.encoding,
+            content: self.content.take(),
+            mime: file.mime.map(String::from),
+            sha256: file.sha256.map(String::from),
+            charset: file.charset.map(String::from),
+            lossy: file.lossy,
+            truncated: file.truncated,
+            outline: file.outline,
+            conversion: file.conversion.map(String::from),
+            lines: file.line_numbers.then_some(file.line_count),
+        };
+
+        // JSON records are comma-separated inside the array, JSONL records are newline-terminated
+        if !self.lines && self.written > 0 {
+            write!(output, ",")?;
+        }
+        serde_json::to_writer(&mut *output, &record)?;
+        if self.lines {
+            writeln!(output)?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn end_document(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        if !self.lines {
+            writeln!(output, "]}}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps every file in a numbered `<document>` element inside a root
+/// `<documents>` element, with the path and content XML-escaped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XmlFormatter;
+
+impl OutputFormatter for XmlFormatter {
+    fn begin_document(&mut self, output: &mut dyn Write, _directory: &Path) -> io::Result<()> {
+        writeln!(output, "<documents>")
+    }
+
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        write!(output, "<document index=\"{}\"", file.index)?;
+        if file.line_numbers && file.encoding == ContentEncoding::Utf8 {
+            write!(output, " lines=\"{}\"", file.line_count)?;
+        }
+        if let Some(charset) = file.charset {
+            write!(output, " charset=\"{}\"", escape_xml(charset))?;
+        }
+        if let Some(conversion) = file.conversion {
+            write!(output, " conversion=\"{}\"", escape_xml(conversion))?;
+        }
+        if file.outline {
+            write!(output, " outline=\"true\"")?;
+        }
+        writeln!(output, ">")?;
+        writeln!(output, "<source>{}</source>", escape_xml(&file.path.display().to_string()))
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, _file: &FileInfo, content: &str) -> io::Result<()> {
+        writeln!(output, "<document_content>{}</document_content>", escape_xml(content))
+    }
+
+    fn end_file(&mut self, output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        writeln!(output, "</document>")
+    }
+
+    fn end_document(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "</documents>")
+    }
+}
+
+/// Writes a self-extracting POSIX `sh` archive.
+///
+/// Running the script with `sh` recreates every file relative to the current
+/// directory. Text is written through quoted heredocs whose terminators never
+/// appear in the content, and non-UTF-8 files are embedded as base64 and
+/// decoded with `base64 -d`.
+#[derive(Debug, Clone, Default)]
+pub struct ShellArchiveFormatter {
+    written: usize,
+}
+
+impl OutputFormatter for ShellArchiveFormatter {
+    fn encode_binary(&self) -> bool {
+        true
+    }
+
+    fn begin_document(&mut self, output: &mut dyn Write, directory: &Path) -> io::Result<()> {
+        writeln!(output, "#!/bin/sh")?;
+        writeln!(output, "# Self-extracting archive of {} generated by flatten.", comment_text(&directory.display().to_string()))?;
+        writeln!(output, "# Run `sh <this file>` to recreate the files under the current directory.")?;
+        writeln!(output, "set -e")?;
+        writeln!(output)
+    }
+
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        writeln!(output, "# {}", comment_text(&file.path.display().to_string()))?;
+        if let Some(parent) = file.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            writeln!(output, "mkdir -p -- {}", shell_quote(&parent.display().to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        let target = shell_quote(&file.path.display().to_string());
+
+        if content.is_empty() {
+            return writeln!(output, ": > {}", target);
+        }
+
+        // NUL bytes cannot pass through a heredoc, so fall back to base64 for them too
+        let (encoded, base64) = match file.encoding {
+            ContentEncoding::Base64 => (content.to_string(), true),
+            _ if content.contains('\0') => (BASE64.encode(content), true),
+            _ => (String::new(), false),
+        };
+
+        if base64 {
+            let terminator = heredoc_terminator(&encoded);
+            writeln!(output, "base64 -d > {} <<'{}'", target, terminator)?;
+            for chunk in encoded.as_bytes().chunks(76) {
+                writeln!(output, "{}", String::from_utf8_lossy(chunk))?;
+            }
+            return writeln!(output, "{}", terminator);
+        }
+
+        // A heredoc always ends with a newline, so a final partial line is appended with printf
+        let (lines, tail) = match content.rfind('\n') {
+            Some(i) => (&content[..=i], &content[i + 1..]),
+            None => ("", content),
+        };
+        if lines.is_empty() {
+            writeln!(output, ": > {}", target)?;
+        } else {
+            let terminator = heredoc_terminator(lines);
+            write!(output, "cat > {} <<'{}'\n{}{}\n", target, terminator, lines, terminator)?;
+        }
+        if !tail.is_empty() {
+            writeln!(output, "printf '%s' {} >> {}", shell_quote(tail), target)?;
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self, output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        self.written += 1;
+        writeln!(output)
+    }
+
+    fn end_document(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "echo 'Extracted {} file(s).'", self.written)?;
+        writeln!(output, "exit 0")
+    }
+}
+
+/// Quotes a string for `sh` using single quotes.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Escapes the control characters of a string written in a `#` comment, so
+/// that a newline in a file name cannot end the comment and run as a command.
+fn comment_text(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { c.escape_default().to_string() } else { c.to_string() }).collect()
+}
+
+/// Returns a heredoc terminator that does not occur as a line of `content`.
+fn heredoc_terminator(content: &str) -> String {
+    let mut terminator = "FLATTEN_EOF".to_string();
+    let mut suffix = 0;
+    while content.lines().any(|line| line == terminator) {
+        suffix += 1;
+        terminator = format!("FLATTEN_EOF_{}", suffix);
+    }
+    terminator
+}
+
+/// Returns a backtick fence that cannot be closed by anything inside `text`.
+///
+/// The fence is three backticks, or one longer than the longest run of
+/// backticks in the content, so Markdown files with their own fenced examples
+/// are read back unambiguously by renderers and by `unflatten`.
+pub fn fence_for(text: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+/// Escapes text for inclusion in XML character data or attribute values.
+///
+/// The five predefined entities are replaced so file contents containing markup,
+/// such as HTML templates or other XML, cannot terminate the surrounding element.
+/// Control characters XML 1.0 forbids, which cannot be escaped either, are
+/// replaced with U+FFFD. The same escaping is valid for HTML text and attributes.
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if is_forbidden_in_xml(c) => escaped.push(char::REPLACEMENT_CHARACTER),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns `true` for the C0 control characters other than tab, line feed and
+/// carriage return, which may not appear in an XML 1.0 document at all.
+pub(crate) fn is_forbidden_in_xml(c: char) -> bool {
+    c < ' ' && !matches!(c, '\t' | '\n' | '\r')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fence_for() {
+        assert_eq!(fence_for("plain text"), "```");
+        assert_eq!(fence_for("inline `code` and ``more``"), "```");
+        assert_eq!(fence_for("```rust\nfn main() {}\n```\n"), "````");
+        assert_eq!(fence_for("`````\n"), "``````");
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml("a < b && c > 'd' \"e\""), "a &lt; b &amp;&amp; c &gt; &apos;d&apos; &quot;e&quot;");
+        assert_eq!(escape_xml("plain text"), "plain text");
+        assert_eq!(escape_xml("bell\u{7}\tnul\0\r\n"), "bell\u{FFFD}\tnul\u{FFFD}\r\n");
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("plain.txt"), "'plain.txt'");
+        assert_eq!(shell_quote("it's here"), "'it'\\''s here'");
+    }
+
+    #[test]
+    fn test_heredoc_terminator() {
+        assert_eq!(heredoc_terminator("hello\n"), "FLATTEN_EOF");
+        assert_eq!(heredoc_terminator("FLATTEN_EOF\nFLATTEN_EOF_1\n"), "FLATTEN_EOF_2");
+    }
+
+    #[test]
+    fn test_markdown_formatter() {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let info = FileInfo {
+            index: 1,
+            path: Path::new("src/main.rs"),
+            syntax: ss.find_syntax_by_extension("rs").unwrap(),
+            syntax_set: &ss,
+            size: 13,
+            line_count: 1,
+            modified: None,
+            executable: false,
+            encoding: ContentEncoding::Utf8,
+            mime: None,
+            sha256: None,
+            charset: None,
+            lossy: false,
+            truncated: false,
+            comments_stripped: false,
+            outline: false,
+            conversion: None,
+            line_numbers: false,
+        };
+
+        let mut output = Vec::new();
+        let mut formatter = MarkdownFormatter;
+        formatter.begin_file(&mut output, &info).unwrap();
+        formatter.write_content(&mut output, &info, "fn main() {}\n").unwrap();
+        formatter.end_file(&mut output, &info).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "## src/main.rs\n```rust\nfn main() {}\n\n```\n\n");
+    }
+}
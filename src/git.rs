@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use git2::{Delta, Diff, DiffOptions, FileMode, ObjectType, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use tempfile::TempDir;
+use crate::file_processing::FLATTEN_IGNORE_FILE;
+use crate::rules::{self, Rule};
+use crate::types::{ExcludeList, GitChanges, GitSelection, IncludeList, ProcessOptions};
+
+/// The ignore files of a revision, in the order the walk ranks them, which are
+/// written out from the flattened directory and those above it so they still apply.
+const IGNORE_FILE_NAMES: &[&str] = &[FLATTEN_IGNORE_FILE, ".ignore", ".gitignore"];
+
+/// Reads the changes a `GitSelection` asks for from the repository containing
+/// `directory`.
+///
+/// Only the local repository is read; no `git` process is started and no
+/// remote is contacted. Paths outside `directory` are left out.
+///
+/// # Arguments
+///
+/// * `directory` - The canonical directory being flattened, anywhere inside a
+///   repository's working copy.
+/// * `selection` - The kinds of change to select.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if `directory` is not inside a working copy
+/// or `changed_since` names no commit, and an error if the repository cannot
+/// be read.
+pub fn changes(directory: &Path, selection: &GitSelection) -> io::Result<GitChanges> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let repo = Repository::discover(directory).map_err(|_| invalid(format!("{} is not inside a git repository", directory.display())))?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| invalid(format!("{} is a bare repository", repo.path().display())))?
+        .canonicalize()?;
+    let prefix = workdir_prefix(&repo, &workdir, directory)?;
+
+    let mut changes = GitChanges::default();
+    if let Some(rev) = &selection.changed_since {
+        let tree = repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_tree())
+            .map_err(|_| invalid(format!("unknown revision '{}'", rev)))?;
+        let diff = repo.diff_tree_to_workdir_with_index(Some(&tree), Some(&mut DiffOptions::new())).map_err(git_error)?;
+        collect(&diff, &prefix, &mut changes, |_| true);
+    }
+    if selection.staged {
+        // An unborn branch has no commit yet, so everything in the index is staged
+        let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+        let diff = repo.diff_tree_to_index(head.as_ref(), None, Some(&mut DiffOptions::new())).map_err(git_error)?;
+        collect(&diff, &prefix, &mut changes, |_| true);
+    }
+    if selection.untracked {
+        let mut options = DiffOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true);
+        let diff = repo.diff_index_to_workdir(None, Some(&mut options)).map_err(git_error)?;
+        collect(&diff, &prefix, &mut changes, |status| status == Delta::Untracked);
+    }
+    Ok(changes)
+}
+
+/// The tree of a revision written out to a temporary directory, which is
+/// removed when this is dropped.
+#[derive(Debug)]
+pub struct RevisionTree {
+    dir: TempDir,
+    prefix: PathBuf,
+    /// The symbolic links that were left out, relative to the flattened directory.
+    pub links: Vec<PathBuf>,
+    /// The submodules that were left out, relative to the flattened directory.
+    pub submodules: Vec<PathBuf>,
+}
+
+impl RevisionTree {
+    /// Returns the copy of the flattened directory inside the written-out tree.
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join(&self.prefix)
+    }
+}
+
+/// Writes the tree of a commit out to a temporary directory, reading it from
+/// the object database without touching the working copy or the index.
+///
+/// Only the part of the tree under `directory` is written, together with the
+/// ignore files of the directories above it. Files the walk is sure to skip
+/// are never written; see `RevisionFilter`. Files get the commit's time as
+/// their modification time and keep their executable bit, while symbolic links
+/// and submodules are left out and listed in the returned `RevisionTree`. An
+/// empty `.git` directory marks the root, so the revision's `.gitignore` files
+/// apply when the copy is walked.
+///
+/// # Arguments
+///
+/// * `directory` - The canonical directory being flattened, anywhere inside a
+///   repository's working copy or a bare repository.
+/// * `rev` - A commit, branch, tag or other revision git understands.
+/// * `exclude` - The exclude rules, relative to `directory`.
+/// * `include` - The include rules, relative to `directory`.
+/// * `options` - The hidden-file and ignore-file options of the walk.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if `directory` is not inside a repository's
+/// working copy, `rev` names no commit or the directory does not exist in it,
+/// and an error if the objects cannot be read or written out.
+pub fn export_revision(
+    directory: &Path,
+    rev: &str,
+    exclude: &ExcludeList,
+    include: &IncludeList,
+    options: &ProcessOptions,
+) -> io::Result<RevisionTree> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let repo = Repository::discover(directory).map_err(|_| invalid(format!("{} is not inside a git repository", directory.display())))?;
+    let prefix = match repo.workdir() {
+        Some(workdir) => workdir_prefix(&repo, &workdir.canonicalize()?, directory)?,
+        None => PathBuf::new(),
+    };
+    let commit = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| invalid(format!("unknown revision '{}'", rev)))?;
+    let tree = commit.tree().map_err(git_error)?;
+    if !prefix.as_os_str().is_empty() && tree.get_path(&prefix).is_err() {
+        return Err(invalid(format!("{} does not exist at revision '{}'", prefix.display(), rev)));
+    }
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(commit.time().seconds().max(0) as u64);
+
+    let dir = tempfile::Builder::new().prefix("flatten-rev-").tempdir()?;
+    fs::create_dir(dir.path().join(".git"))?;
+    let mut filter = RevisionFilter { repo: &repo, tree: &tree, root: dir.path(), prefix: &prefix, exclude, include, options, ignores: HashMap::new() };
+    let (mut links, mut submodules) = (Vec::new(), Vec::new());
+    let mut result = Ok(());
+    let walked = tree.walk(TreeWalkMode::PreOrder, |parent, entry| {
+        let name = entry.name().unwrap_or_default();
+        let path = Path::new(parent).join(name);
+        let inside = path.starts_with(&prefix);
+        let rel_path = || path.strip_prefix(&prefix).unwrap_or(&path).to_path_buf();
+        match entry.kind() {
+            // Only the directories on the way down to the flattened one and those inside it are entered
+            Some(ObjectType::Tree) if inside && filter.keeps(&path, true) => TreeWalkResult::Ok,
+            Some(ObjectType::Tree) if !inside && prefix.starts_with(&path) => TreeWalkResult::Ok,
+            Some(ObjectType::Tree) => TreeWalkResult::Skip,
+            // Ignore files are always written, even hidden ones, since the walk reads them
+            Some(ObjectType::Blob) if IGNORE_FILE_NAMES.contains(&name) || (inside && filter.keeps(&path, false)) => {
+                if entry.filemode() == i32::from(FileMode::Link) {
+                    if inside {
+                        links.push(rel_path());
+                    }
+                    return TreeWalkResult::Ok;
+                }
+                let executable = entry.filemode() == i32::from(FileMode::BlobExecutable);
+                result = entry
+                    .to_object(&repo)
+                    .and_then(|object| object.peel_to_blob())
+                    .map_err(git_error)
+                    .and_then(|blob| write_blob(&dir.path().join(&path), blob.content(), executable, modified));
+                if result.is_ok() { TreeWalkResult::Ok } else { TreeWalkResult::Abort }
+            }
+            Some(ObjectType::Commit) if inside && filter.keeps(&path, true) => {
+                submodules.push(rel_path());
+                TreeWalkResult::Ok
+            }
+            _ => TreeWalkResult::Ok,
+        }
+    });
+    // A failed write aborts the walk, which then reports a less useful error of its own
+    result?;
+    walked.map_err(git_error)?;
+
+    Ok(RevisionTree { dir, prefix, links, submodules })
+}
+
+/// Returns `directory` relative to the working copy of `repo`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error if `directory` lies inside the repository's
+/// `.git` directory or otherwise outside `workdir`.
+fn workdir_prefix(repo: &Repository, workdir: &Path, directory: &Path) -> io::Result<PathBuf> {
+    let git_dir = repo.path().canonicalize()?;
+    match directory.strip_prefix(workdir) {
+        Ok(prefix) if !directory.starts_with(&git_dir) => Ok(prefix.to_path_buf()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not inside the working copy {}", directory.display(), workdir.display()),
+        )),
+    }
+}
+
+/// Decides which entries of a revision are worth writing out.
+///
+/// Only entries the walk over the written-out tree is sure to skip are left
+/// out, so the walk still decides everything else: paths the include and
+/// exclude rules reject, paths the revision's ignore files ignore, and hidden
+/// paths when hidden files are skipped. An entry that a `!` pattern of any
+/// ignore file re-includes, or that lies on the way to or below a literal
+/// include, is always kept.
+struct RevisionFilter<'a> {
+    repo: &'a Repository,
+    tree: &'a Tree<'a>,
+    /// The root of the written-out tree, which the ignore files are matched under.
+    root: &'a Path,
+    /// The flattened directory, relative to the root.
+    prefix: &'a Path,
+    exclude: &'a ExcludeList,
+    include: &'a IncludeList,
+    options: &'a ProcessOptions,
+    /// The ignore files of each directory read so far, relative to the root.
+    ignores: HashMap<PathBuf, Vec<Gitignore>>,
+}
+
+impl RevisionFilter<'_> {
+    /// Returns `true` if the entry at `path`, relative to the root, may be
+    /// selected by the walk.
+    fn keeps(&mut self, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel_path) = path.strip_prefix(self.prefix) else {
+            return true;
+        };
+        let literal = |literal: &Path| !literal.as_os_str().is_empty() && (rel_path.starts_with(literal) || literal.starts_with(rel_path));
+        if rel_path.as_os_str().is_empty() || self.include.0.iter().filter_map(Rule::literal_path).any(literal) {
+            return true;
+        }
+        let full_path = self.root.join(path);
+        let (mut ignored, mut whitelisted) = (false, false);
+        for dir in path.ancestors().skip(1) {
+            if !self.options.ignore.parents && !dir.starts_with(self.prefix) {
+                continue;
+            }
+            for matcher in self.matchers(dir) {
+                match matcher.matched(&full_path, is_dir) {
+                    Match::Ignore(_) => ignored = true,
+                    Match::Whitelist(_) => whitelisted = true,
+                    Match::None => {}
+                }
+            }
+        }
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if !whitelisted && (ignored || (hidden && !self.options.allow_hidden)) {
+            return false;
+        }
+        // The rules come last, so that they only see the paths the walk shows them
+        // and rules matching nothing are still reported
+        if is_dir {
+            !rules::skips_directory(rel_path, self.exclude, self.include)
+        } else {
+            rules::decide(rel_path, self.exclude, self.include).included
+        }
+    }
+
+    /// Returns the matchers of the enabled ignore files in `dir`, reading them
+    /// from the revision the first time.
+    fn matchers(&mut self, dir: &Path) -> &[Gitignore] {
+        let ignore = &self.options.ignore;
+        let enabled = [ignore.flattenignore, ignore.dot_ignore, ignore.gitignore];
+        let (repo, tree, root) = (self.repo, self.tree, self.root);
+        self.ignores.entry(dir.to_path_buf()).or_insert_with(|| {
+            IGNORE_FILE_NAMES
+                .iter()
+                .zip(enabled)
+                .filter(|(_, enabled)| *enabled)
+                .filter_map(|(name, _)| {
+                    let blob = tree.get_path(&dir.join(name)).and_then(|entry| entry.to_object(repo)).and_then(|object| object.peel_to_blob()).ok()?;
+                    let mut builder = GitignoreBuilder::new(root.join(dir));
+                    // Like the walk, a line that is not a valid glob is passed over
+                    for line in String::from_utf8_lossy(blob.content()).lines() {
+                        let _ = builder.add_line(Some(root.join(dir).join(name)), line);
+                    }
+                    builder.build().ok()
+                })
+                .collect()
+        })
+    }
+}
+
+/// Writes one file of a written-out revision.
+fn write_blob(path: &Path, content: &[u8], executable: bool, modified: SystemTime) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    #[cfg(unix)]
+    if executable {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(not(unix))]
+    let _ = executable;
+    File::options().write(true).open(path)?.set_modified(modified)
+}
+
+/// Adds the files of `diff` whose status `keep` accepts to `changes`, relative
+/// to `prefix`, the flattened directory within the working copy.
+fn collect(diff: &Diff, prefix: &Path, changes: &mut GitChanges, keep: impl Fn(Delta) -> bool) {
+    for delta in diff.deltas().filter(|delta| keep(delta.status())) {
+        let (file, set) = match delta.status() {
+            Delta::Deleted => (delta.old_file(), &mut changes.deleted),
+            _ => (delta.new_file(), &mut changes.paths),
+        };
+        if let Some(path) = file.path().and_then(|path| path.strip_prefix(prefix).ok()) {
+            set.insert(path.to_path_buf());
+        }
+    }
+}
+
+/// Converts a libgit2 error into an `io::Error` carrying its message.
+pub(crate) fn git_error(error: git2::Error) -> io::Error {
+    io::Error::other(error.message().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use crate::types::IgnoreOptions;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn commit_all(repo: &Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, parent.as_ref().into_iter().collect::<Vec<_>>().as_slice()).unwrap();
+    }
+
+    fn export(directory: &Path, rev: &str) -> io::Result<RevisionTree> {
+        let exclude = ExcludeList::new(directory, vec![]);
+        let include = IncludeList::new(directory, vec![]);
+        export_revision(directory, rev, &exclude, &include, &ProcessOptions::default())
+    }
+
+    #[test]
+    fn test_changes_by_mode() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir_path).unwrap();
+        fs::create_dir(dir_path.join("src")).unwrap();
+        fs::write(dir_path.join("src").join("lib.rs"), "pub fn a() {}\n").unwrap();
+        fs::write(dir_path.join("src").join("old.rs"), "old\n").unwrap();
+        fs::write(dir_path.join("README.md"), "readme\n").unwrap();
+        commit_all(&repo, "initial");
+
+        fs::write(dir_path.join("src").join("lib.rs"), "pub fn b() {}\n").unwrap();
+        fs::remove_file(dir_path.join("src").join("old.rs")).unwrap();
+        commit_all(&repo, "second");
+        fs::write(dir_path.join("README.md"), "staged\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("README.md")).unwrap();
+        index.write().unwrap();
+        fs::write(dir_path.join("src").join("new.rs"), "new\n").unwrap();
+
+        let paths = |selection: GitSelection| changes(&dir_path, &selection).unwrap();
+        let set = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<BTreeSet<_>>();
+
+        let since = paths(GitSelection { changed_since: Some("HEAD~1".to_string()), ..GitSelection::default() });
+        assert_eq!(since.paths, set(&["README.md", "src/lib.rs"]));
+        assert_eq!(since.deleted, set(&["src/old.rs"]));
+        assert_eq!(paths(GitSelection { staged: true, ..GitSelection::default() }).paths, set(&["README.md"]));
+        assert_eq!(paths(GitSelection { untracked: true, ..GitSelection::default() }).paths, set(&["src/new.rs"]));
+
+        // Paths are relative to a subdirectory being flattened
+        let sub = changes(&dir_path.join("src"), &GitSelection { changed_since: Some("HEAD~1".to_string()), untracked: true, staged: false }).unwrap();
+        assert_eq!(sub.paths, set(&["lib.rs", "new.rs"]));
+
+        let err = changes(&dir_path, &GitSelection { changed_since: Some("no-such-ref".to_string()), ..GitSelection::default() }).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_export_revision() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir_path).unwrap();
+        fs::create_dir_all(dir_path.join("app").join("src")).unwrap();
+        fs::create_dir(dir_path.join("docs")).unwrap();
+        fs::write(dir_path.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir_path.join("app").join("src").join("main.rs"), "v1\n").unwrap();
+        fs::write(dir_path.join("docs").join("guide.md"), "guide\n").unwrap();
+        commit_all(&repo, "initial");
+        fs::write(dir_path.join("app").join("src").join("main.rs"), "v2\n").unwrap();
+        fs::write(dir_path.join("app").join("added.rs"), "new\n").unwrap();
+
+        let tree = export(&dir_path.join("app"), "HEAD").unwrap();
+        let root = tree.path();
+        assert!(root.ends_with("app"));
+        assert_eq!(fs::read_to_string(root.join("src").join("main.rs")).unwrap(), "v1\n");
+        assert!(!root.join("added.rs").exists());
+        // Ignore files above the directory come along, other directories do not
+        let top = root.parent().unwrap();
+        assert_eq!(fs::read_to_string(top.join(".gitignore")).unwrap(), "*.log\n");
+        assert!(top.join(".git").is_dir() && !top.join("docs").exists());
+
+        // The working copy is left as it was
+        assert_eq!(fs::read_to_string(dir_path.join("app").join("src").join("main.rs")).unwrap(), "v2\n");
+        let path = root.clone();
+        drop(tree);
+        assert!(!path.exists());
+
+        assert_eq!(export(&dir_path, "no-such-ref").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        fs::create_dir(dir_path.join("later")).unwrap();
+        assert_eq!(export(&dir_path.join("later"), "HEAD").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        // The repository's own metadata is not part of any revision
+        let git_dir = dir_path.join(".git").join("objects");
+        assert_eq!(export(&git_dir, "HEAD").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(changes(&git_dir, &GitSelection { untracked: true, ..GitSelection::default() }).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_export_revision_skips_unselected_entries() {
+        let temp_dir = tempdir().unwrap();
+        let dir_path = temp_dir.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir_path).unwrap();
+        let app = dir_path.join("app");
+        fs::create_dir_all(app.join("vendor")).unwrap();
+        fs::create_dir_all(app.join("build")).unwrap();
+        fs::write(app.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(app.join("image.bin"), [0u8; 64]).unwrap();
+        fs::write(app.join("vendor").join("lib.rs"), "vendored\n").unwrap();
+        fs::write(app.join("build").join("out.o"), "object\n").unwrap();
+        fs::write(app.join("debug.log"), "noise\n").unwrap();
+        fs::write(app.join("keep.log"), "kept\n").unwrap();
+        fs::write(app.join(".env"), "SECRET=1\n").unwrap();
+        commit_all(&repo, "initial");
+        // Files committed before they were ignored stay in the tree
+        fs::write(app.join(".gitignore"), "build/\n*.log\n!keep.log\n").unwrap();
+        commit_all(&repo, "ignore");
+
+        // Add a symbolic link and a submodule to the committed tree
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let app_tree = repo.find_tree(head.tree().unwrap().get_path(Path::new("app")).unwrap().id()).unwrap();
+        let mut builder = repo.treebuilder(Some(&app_tree)).unwrap();
+        builder.insert("link.rs", repo.blob(b"main.rs").unwrap(), i32::from(FileMode::Link)).unwrap();
+        builder.insert("sub", head.id(), i32::from(FileMode::Commit)).unwrap();
+        let mut root = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+        root.insert("app", builder.write().unwrap(), i32::from(FileMode::Tree)).unwrap();
+        let tree = repo.find_tree(root.write().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "links", &tree, &[&head]).unwrap();
+
+        let exclude = ExcludeList::new(&app, vec![PathBuf::from("*.bin"), PathBuf::from("vendor")]);
+        let include = IncludeList::new(&app, vec![]);
+        let exported = export_revision(&app, "HEAD", &exclude, &include, &ProcessOptions::default()).unwrap();
+        let root = exported.path();
+        assert!(root.join("main.rs").is_file() && root.join("keep.log").is_file() && root.join(".gitignore").is_file());
+        for skipped in ["image.bin", "vendor", "build", "debug.log", ".env", "link.rs", "sub"] {
+            assert!(!root.join(skipped).exists(), "{} was written", skipped);
+        }
+        assert_eq!(exported.links, vec![PathBuf::from("link.rs")]);
+        assert_eq!(exported.submodules, vec![PathBuf::from("sub")]);
+
+        // Hidden files are written when they may be selected, and nothing is
+        // left out when no ignore files are read
+        let options = ProcessOptions { allow_hidden: true, ignore: IgnoreOptions::NONE, ..ProcessOptions::default() };
+        let exported = export_revision(&app, "HEAD", &exclude, &include, &options).unwrap();
+        for written in [".env", "build/out.o", "debug.log"] {
+            assert!(exported.path().join(written).is_file(), "{} was not written", written);
+        }
+    }
+}
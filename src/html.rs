@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::util::LinesWithEndings;
+use crate::formatter::{escape_xml, is_forbidden_in_xml, FileInfo, OutputFormatter};
+use crate::types::ContentEncoding;
+
+/// The syntect theme used when no theme is requested explicitly.
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Looks up a theme from syntect's bundled defaults by name.
+///
+/// # Arguments
+///
+/// * `themes` - The `ThemeSet` loaded from syntect's defaults.
+/// * `name` - The theme name, e.g. `InspiredGitHub` or `base16-ocean.dark`.
+///
+/// # Errors
+///
+/// Returns an `InvalidInput` error listing the available themes if `name`
+/// is not part of the set.
+pub fn find_theme<'a>(themes: &'a ThemeSet, name: &str) -> io::Result<&'a Theme> {
+    themes.themes.get(name).ok_or_else(|| {
+        let available: Vec<&str> = themes.themes.keys().map(String::as_str).collect();
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown theme `{}`, expected one of: {}", name, available.join(", ")),
+        )
+    })
+}
+
+/// Formats a syntect `Color` as a CSS `rgba()` value.
+fn css_color(color: Option<Color>, fallback: &str) -> String {
+    match color {
+        Some(c) => format!("rgba({}, {}, {}, {:.3})", c.r, c.g, c.b, c.a as f32 / 255.0),
+        None => fallback.to_string(),
+    }
+}
+
+/// Writes the opening of the HTML report, including the inline stylesheet.
+///
+/// The page background and foreground of the code blocks are taken from the
+/// selected theme, so the report renders the same way offline as it does when
+/// attached to a ticket.
+fn write_header(output: &mut dyn Write, title: &str, theme: &Theme) -> io::Result<()> {
+    let background = css_color(theme.settings.background, "#ffffff");
+    let foreground = css_color(theme.settings.foreground, "#000000");
+    let gutter = css_color(theme.settings.gutter_foreground, "#999999");
+
+    writeln!(output, "<!DOCTYPE html>")?;
+    writeln!(output, "<html lang=\"en\">")?;
+    writeln!(output, "<head>")?;
+    writeln!(output, "<meta charset=\"utf-8\">")?;
+    writeln!(output, "<title>{}</title>", escape_xml(title))?;
+    writeln!(output, "<style>")?;
+    writeln!(output, "body {{ margin: 0; font-family: sans-serif; }}")?;
+    writeln!(output, "nav {{ position: fixed; top: 0; bottom: 0; left: 0; width: 18rem; overflow: auto; padding: 1rem; box-sizing: border-box; border-right: 1px solid #ccc; font-size: 0.9rem; }}")?;
+    writeln!(output, "nav ul {{ list-style: none; margin: 0; padding-left: 1rem; }}")?;
+    writeln!(output, "nav summary {{ cursor: pointer; }}")?;
+    writeln!(output, "main {{ margin-left: 18rem; padding: 1rem 2rem; }}")?;
+    writeln!(output, "section h2 {{ font-size: 1.1rem; }}")?;
+    writeln!(output, "section .meta {{ color: #666; font-weight: normal; font-size: 0.85rem; }}")?;
+    writeln!(output, "pre {{ background: {}; color: {}; padding: 0.5rem; overflow: auto; }}", background, foreground)?;
+    writeln!(output, "pre .ln {{ display: inline-block; width: 4em; margin-right: 1em; text-align: right; color: {}; text-decoration: none; user-select: none; }}", gutter)?;
+    writeln!(output, "pre .line:target {{ outline: 1px solid {}; }}", gutter)?;
+    writeln!(output, "</style>")?;
+    writeln!(output, "</head>")?;
+    writeln!(output, "<body>")?;
+    writeln!(output, "<main>")?;
+    writeln!(output, "<h1>{}</h1>", escape_xml(title))?;
+    Ok(())
+}
+
+/// A directory in the sidebar tree, holding its subdirectories and the
+/// `(name, index)` pairs of the files directly inside it.
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    files: Vec<(String, usize)>,
+}
+
+impl DirNode {
+    /// Inserts a `/`-separated relative path into the tree.
+    fn insert(&mut self, path: &str, index: usize) {
+        let mut node = self;
+        let mut components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let name = components.pop().unwrap_or(path).to_string();
+        for component in components {
+            node = node.dirs.entry(component.to_string()).or_default();
+        }
+        node.files.push((name, index));
+    }
+
+    /// Renders this node's children as nested lists, with each directory
+    /// wrapped in a `<details>` element so it can be collapsed without scripts.
+    fn render(&self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "<ul>")?;
+        for (name, child) in &self.dirs {
+            writeln!(output, "<li><details open><summary>{}/</summary>", escape_xml(name))?;
+            child.render(output)?;
+            writeln!(output, "</details></li>")?;
+        }
+        let mut files = self.files.clone();
+        files.sort();
+        for (name, index) in files {
+            writeln!(output, "<li><a href=\"#file-{}\">{}</a></li>", index, escape_xml(&name))?;
+        }
+        writeln!(output, "</ul>")?;
+        Ok(())
+    }
+}
+
+/// Closes the report and writes the collapsible directory-tree sidebar.
+///
+/// The sidebar is emitted after the file sections so that files can be
+/// streamed to the output as they are walked; CSS pins it to the left edge.
+///
+/// # Arguments
+///
+/// * `output` - The writer receiving the report.
+/// * `paths` - The relative paths of the written files, in index order starting at 1.
+fn write_footer(output: &mut dyn Write, paths: &[String]) -> io::Result<()> {
+    let mut root = DirNode::default();
+    for (i, path) in paths.iter().enumerate() {
+        root.insert(&path.replace('\\', "/"), i + 1);
+    }
+
+    writeln!(output, "</main>")?;
+    writeln!(output, "<nav>")?;
+    writeln!(output, "<strong>Files</strong>")?;
+    root.render(output)?;
+    writeln!(output, "</nav>")?;
+    writeln!(output, "</body>")?;
+    writeln!(output, "</html>")?;
+    Ok(())
+}
+
+/// Renders a self-contained HTML report with syntect highlighting.
+///
+/// Each file becomes a section anchored as `file-<index>` with every line
+/// anchored as `file-<index>-L<line>`, and a collapsible directory tree links
+/// to every section.
+pub struct HtmlFormatter {
+    theme: Theme,
+    paths: Vec<String>,
+}
+
+impl HtmlFormatter {
+    /// Creates a formatter using one of syntect's bundled themes.
+    ///
+    /// # Arguments
+    ///
+    /// * `theme` - The theme name, or `None` for `DEFAULT_THEME`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if the theme does not exist.
+    pub fn new(theme: Option<&str>) -> io::Result<Self> {
+        let themes = ThemeSet::load_defaults();
+        let theme = find_theme(&themes, theme.unwrap_or(DEFAULT_THEME))?.clone();
+        Ok(HtmlFormatter { theme, paths: Vec::new() })
+    }
+}
+
+impl OutputFormatter for HtmlFormatter {
+    fn begin_document(&mut self, output: &mut dyn Write, directory: &Path) -> io::Result<()> {
+        let title = directory.file_name().map_or_else(|| directory.display().to_string(), |n| n.to_string_lossy().into_owned());
+        write_header(output, &title, &self.theme)
+    }
+
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        let anchor = format!("file-{}", file.index);
+        writeln!(output, "<section>")?;
+        writeln!(
+            output,
+            "<h2 id=\"{0}\"><a href=\"#{0}\">{1}</a> <span class=\"meta\">{2}, {3} bytes{4}</span></h2>",
+            anchor,
+            escape_xml(&file.path.display().to_string()),
+            escape_xml(&file.syntax_name()),
+            file.size,
+            file.header_note().map(|note| format!(", {}", escape_xml(&note))).unwrap_or_default()
+        )
+    }
+
+    fn numbers_lines(&self) -> bool {
+        true
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        match file.encoding {
+            ContentEncoding::Utf8 => {}
+            ContentEncoding::NonUtf8 => return writeln!(output, "<p><em>{}</em></p>", escape_xml(content)),
+            // Encoded binary content is shown as is, without highlighting
+            ContentEncoding::Base64 | ContentEncoding::Hexdump => {
+                return writeln!(output, "<pre>{}</pre>", escape_xml(content));
+            }
+        }
+
+        // syntect only escapes markup, so characters no document may hold are replaced first
+        let content = content.replace(is_forbidden_in_xml, "\u{FFFD}");
+        let mut highlighter = HighlightLines::new(file.syntax, &self.theme);
+        write!(output, "<pre>")?;
+        for (number, line) in LinesWithEndings::from(&content).enumerate() {
+            let regions = highlighter.highlight_line(line, file.syntax_set).map_err(io::Error::other)?;
+            let html = styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                .map_err(io::Error::other)?;
+            write!(
+                output,
+                "<span class=\"line\" id=\"file-{0}-L{1}\"><a class=\"ln\" href=\"#file-{0}-L{1}\">{1}</a>{2}</span>",
+                file.index, number + 1, html
+            )?;
+        }
+        writeln!(output, "</pre>")
+    }
+
+    fn end_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        self.paths.push(file.path.display().to_string());
+        writeln!(output, "</section>")
+    }
+
+    fn end_document(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        write_footer(output, &self.paths)
+    }
+}
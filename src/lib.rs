@@ -1,5 +1,24 @@
 // src/lib.rs
 
 // Re-export modules and functions for integration testing
+pub mod binary;
+pub mod bundle;
+pub mod charset;
+pub mod comments;
+pub mod explain;
 pub mod file_processing;
+pub mod formatter;
+pub mod git;
+pub mod html;
+pub mod line_numbers;
+pub mod notebook;
+pub mod outline;
+pub mod patch;
+pub mod rules;
+pub mod secrets;
+pub mod sqlite;
+pub mod summary;
+pub mod template;
+pub mod truncate;
 pub mod types;
+pub mod unflatten;
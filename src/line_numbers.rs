@@ -0,0 +1,107 @@
+/// The separator between the line number gutter and the line itself.
+pub const GUTTER_SEPARATOR: &str = " | ";
+
+/// Returns the original line number of every line of `text`.
+///
+/// For truncated text, `excerpt` gives the number of lines before and after
+/// the elision marker. The head is numbered from 1, the tail continues from
+/// the original line numbers at the end of the file, and the marker has no
+/// number.
+///
+/// # Arguments
+///
+/// * `text` - The content, possibly truncated.
+/// * `line_count` - The number of lines in the whole file.
+/// * `excerpt` - The head and tail line counts of truncated text, or `None`.
+pub fn line_origins(text: &str, line_count: usize, excerpt: Option<(usize, usize)>) -> Vec<Option<usize>> {
+    (0..text.split_inclusive('\n').count())
+        .map(|index| match excerpt {
+            None => Some(index + 1),
+            Some((head, _)) if index < head => Some(index + 1),
+            Some((head, _)) if index == head => None,
+            Some((head, tail)) => Some(line_count - tail + index - head),
+        })
+        .collect()
+}
+
+/// Prefixes every line of `text` with its line number from `numbers`,
+/// right-aligned to the width of the largest number in the file.
+///
+/// Lines whose number is `None`, such as the elision marker of a truncated
+/// file, get an empty gutter, so every number still points at the line in
+/// the original file even after truncation or comment stripping.
+///
+/// # Arguments
+///
+/// * `text` - The content to number.
+/// * `line_count` - The number of lines in the whole file, which sets the gutter width.
+/// * `numbers` - The original line number of every line of `text`, as from `line_origins`.
+///
+/// # Returns
+///
+/// The numbered text, e.g. ` 9 | fn main() {}` and `10 | }` for a ten-line file.
+pub fn number_lines(text: &str, line_count: usize, numbers: &[Option<usize>]) -> String {
+    let width = line_count.max(1).to_string().len();
+    let mut numbered = String::with_capacity(text.len() + (width + GUTTER_SEPARATOR.len()) * numbers.len());
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        match numbers.get(index).copied().flatten() {
+            Some(number) => numbered.push_str(&format!("{:>width$}{}", number, GUTTER_SEPARATOR, width = width)),
+            None => numbered.push_str(&format!("{:>width$}{}", "", GUTTER_SEPARATOR, width = width)),
+        }
+        numbered.push_str(line);
+    }
+    numbered
+}
+
+/// Removes the gutter written by `number_lines` from a single line.
+///
+/// # Returns
+///
+/// * `Some(line)` without its gutter.
+/// * `None` if the line does not start with a gutter.
+pub fn strip_gutter(line: &str) -> Option<&str> {
+    let (gutter, rest) = line.split_once(GUTTER_SEPARATOR)?;
+    gutter.trim_start().chars().all(|c| c.is_ascii_digit()).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_lines() {
+        let text: String = (1..=10).map(|n| format!("line {}\n", n)).collect();
+        let numbered = number_lines(&text, 10, &line_origins(&text, 10, None));
+        assert!(numbered.starts_with(" 1 | line 1\n 2 | line 2\n"));
+        assert!(numbered.ends_with(" 9 | line 9\n10 | line 10\n"));
+
+        // A missing final newline is preserved
+        assert_eq!(number_lines("a\nb", 2, &line_origins("a\nb", 2, None)), "1 | a\n2 | b");
+        assert_eq!(number_lines("", 0, &[]), "");
+    }
+
+    #[test]
+    fn test_number_lines_truncated() {
+        let text = "line 1\nline 2\n… 95 lines omitted …\nline 99\nline 100\n";
+        assert_eq!(
+            number_lines(text, 100, &line_origins(text, 100, Some((2, 2)))),
+            "  1 | line 1\n  2 | line 2\n    | … 95 lines omitted …\n 99 | line 99\n100 | line 100\n"
+        );
+    }
+
+    #[test]
+    fn test_line_origins() {
+        assert_eq!(line_origins("a\nb\n", 2, None), vec![Some(1), Some(2)]);
+        assert_eq!(line_origins("a\n…\ny\nz", 26, Some((1, 2))), vec![Some(1), None, Some(25), Some(26)]);
+    }
+
+    #[test]
+    fn test_strip_gutter() {
+        assert_eq!(strip_gutter(" 9 | fn main() {}"), Some("fn main() {}"));
+        assert_eq!(strip_gutter("10 | a | b"), Some("a | b"));
+        assert_eq!(strip_gutter("   | … 2 lines omitted …"), Some("… 2 lines omitted …"));
+        assert_eq!(strip_gutter("no gutter"), None);
+        assert_eq!(strip_gutter("x | y"), None);
+    }
+}
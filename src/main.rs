@@ -1,13 +1,16 @@
 mod cli;
-mod file_processing;
-mod types;
 
+use std::collections::HashSet;
 use std::io;
 use std::path::PathBuf;
-use cli::Cli;
+use std::process;
+use cli::{Cli, Command};
 use structopt::StructOpt;
 use chrono::Local;
-use types::{ExcludeList, IncludeList};
+use flatten::{explain, file_processing, git, unflatten};
+use flatten::explain::Explainer;
+use flatten::git::RevisionTree;
+use flatten::types::{CommentOptions, ConflictPolicy, ExcludeList, GitSelection, IgnoreOptions, IncludeList, ProcessOptions, SummaryOptions, TruncateOptions, UnflattenOptions};
 use std::fs::canonicalize;
 
 /// Main function for the `flatten` CLI tool, handling argument parsing, directory
@@ -29,12 +32,64 @@ use std::fs::canonicalize;
 /// or if user input fails after exceeding the size limit warning.
 fn main() -> io::Result<()> {
     // Parse command-line arguments into structured options
-    let args = Cli::from_args();
+    let matches = Cli::clap().get_matches();
+    let args = Cli::from_clap(&matches);
+
+    // Subcommands replace the default flattening behaviour, except that
+    // `explain` needs the selection options parsed below
+    let explain_paths = match args.command {
+        Some(Command::Explain { paths }) => Some(paths),
+        Some(command) => return run_command(command),
+        None => None,
+    };
+
     let directory = canonicalize(&args.directory)?;
 
     // Create inclusion and exclusion lists based on CLI arguments
-    let exclude = ExcludeList::new(&directory, args.exclude);
-    let include = IncludeList::new(&directory, args.include);
+    // Rules keep their command-line positions, so the last matching one decides across both lists
+    let positions = |name| matches.indices_of(name).map(Iterator::collect::<Vec<_>>).unwrap_or_default();
+    let exclude = ExcludeList::with_positions(&directory, positions("exclude").into_iter().zip(args.exclude).collect())?;
+    let include = IncludeList::with_positions(&directory, positions("include").into_iter().zip(args.include).collect())?;
+
+    let ignore = if args.no_ignore {
+        IgnoreOptions::NONE
+    } else {
+        IgnoreOptions {
+            flattenignore: !args.no_flattenignore,
+            dot_ignore: !args.no_ignore_dot,
+            gitignore: !args.no_gitignore,
+            global_gitignore: !args.no_global_gitignore,
+            git_exclude: !args.no_git_exclude,
+            parents: !args.no_ignore_parent,
+        }
+    };
+
+    // The changes are read once and shared by the size estimate, the walk and the explain report
+    let selection = GitSelection { changed_since: args.changed_since, staged: args.staged, untracked: args.untracked };
+    let git = if selection.is_active() { Some(git::changes(&directory, &selection)?) } else { None };
+
+    // A revision is written out to a temporary directory and walked from there, while
+    // the rules and explained paths stay relative to the directory given
+    let walk = ProcessOptions { allow_hidden: args.allow_hidden, ignore, git, ..ProcessOptions::default() };
+    let revision = args.rev.as_deref().map(|rev| git::export_revision(&directory, rev, &exclude, &include, &walk)).transpose()?;
+    let root = revision.as_ref().map_or_else(|| directory.clone(), RevisionTree::path);
+    if let Some(revision) = &revision {
+        for path in &revision.links {
+            eprintln!("note: {} is a symbolic link and was skipped", path.display());
+        }
+        for path in &revision.submodules {
+            eprintln!("note: {} is a submodule and was skipped", path.display());
+        }
+    }
+
+    if let Some(paths) = explain_paths {
+        let mut explainer = Explainer::new(&root, &exclude, &include, &walk);
+        for path in paths {
+            let rel_path = explain::relative_path(&directory, &path)?;
+            println!("{}", explainer.explain(&rel_path)?);
+        }
+        return Ok(());
+    }
     
     // Determine output file path, generating a timestamped default if not specified
     let output_file = match args.output {
@@ -44,12 +99,53 @@ fn main() -> io::Result<()> {
             let current_dir = directory.file_stem()
                 .and_then(|os_str| os_str.to_str())
                 .unwrap_or("root");
-            PathBuf::from(format!("flatten-{}-{}.txt", current_dir, datetime))
+            PathBuf::from(format!("flatten-{}-{}.{}", current_dir, datetime, args.format.extension()))
         }
     };
 
-    // Estimate directory size and confirm with the user if it exceeds a preset limit (10 MB)
-    let directory_size = file_processing::calculate_directory_size(&directory, &exclude, &include, args.allow_hidden)?;
+    let options = ProcessOptions {
+        format: args.format,
+        theme: args.theme,
+        template: args.template,
+        binary: args.binary,
+        lossy: args.lossy,
+        truncate: TruncateOptions {
+            max_bytes: args.max_file_bytes,
+            max_lines: args.max_file_lines,
+            head_lines: args.head_lines,
+            tail_lines: args.tail_lines,
+        },
+        line_numbers: args.line_numbers,
+        comments: CommentOptions {
+            strip: args.strip_comments,
+            keep_doc: args.keep_doc_comments,
+            keep_license: args.keep_license,
+        },
+        outline: args.outline,
+        notebook_outputs: args.notebook_outputs,
+        summary: SummaryOptions {
+            enabled: args.summarize_data,
+            min_bytes: args.summary_min_bytes,
+            rows: args.summary_rows,
+            samples: args.summary_samples,
+        },
+        sqlite_rows: args.sqlite_rows,
+        keep_secrets: args.keep_secrets,
+        ..walk
+    };
+
+    // Estimate the output size after truncation and summaries, and confirm with the
+    // user if it exceeds a preset limit (10 MB)
+    let directory_size = file_processing::estimate_output_size(&root, &exclude, &include, &options)?;
+    // Rules that matched nothing during the walk are most likely mistyped
+    for rule in exclude.0.iter().chain(&include.0).filter(|rule| !rule.has_matched()) {
+        eprintln!("warning: pattern '{}' matched no files", rule.pattern);
+    }
+    // Deleted files cannot be flattened, but are still part of the change under review
+    for path in options.git.iter().flat_map(|changes| &changes.deleted) {
+        eprintln!("note: {} was deleted", path.display());
+    }
+
     const SIZE_LIMIT: u64 = 10 * 1024 * 1024; // 10 MB
     if directory_size > SIZE_LIMIT {
         println!("Warning: The directory size is {} bytes. Do you want to continue? (y/n)", directory_size);
@@ -61,7 +157,59 @@ fn main() -> io::Result<()> {
     }
 
     // Perform file flattening and generate the output file
-    file_processing::process_files(&directory, &output_file, &exclude, &include, args.allow_hidden)?;
+    let report = file_processing::process_files_with_options(&root, &output_file, &exclude, &include, &options)?;
+
+    if args.explain {
+        for explanation in explain::explain_tree(&root, Some(&output_file), &exclude, &include, &options)? {
+            println!("{}", explanation);
+        }
+    }
+
+    // Summarize the secrets on stderr so they are seen even when stdout is piped
+    if !report.redactions.is_empty() {
+        let verb = if args.keep_secrets { "found" } else { "redacted" };
+        for redaction in &report.redactions {
+            eprintln!("warning: {} {} at {}:{}", verb, redaction.kind, redaction.path.display(), redaction.line);
+        }
+        let files: HashSet<&PathBuf> = report.redactions.iter().map(|r| &r.path).collect();
+        eprintln!("{} {} secret(s) in {} file(s)", verb, report.redactions.len(), files.len());
+        if args.fail_on_secret {
+            process::exit(1);
+        }
+    }
 
     Ok(())
 }
+
+/// Runs a subcommand selected on the command line.
+///
+/// # Errors
+///
+/// Propagates any error returned by the subcommand's library function.
+fn run_command(command: Command) -> io::Result<()> {
+    match command {
+        Command::Explain { .. } => unreachable!("explain is run by main with the selection options"),
+        Command::Unflatten { input, target, dry_run, overwrite, skip_existing } => {
+            let conflict = if overwrite {
+                ConflictPolicy::Overwrite
+            } else if skip_existing {
+                ConflictPolicy::SkipExisting
+            } else {
+                ConflictPolicy::Fail
+            };
+            let report = unflatten::unflatten(&input, &target, &UnflattenOptions { dry_run, conflict })?;
+
+            let verb = if dry_run { "would write" } else { "wrote" };
+            for path in &report.written {
+                println!("{} {}", verb, target.join(path).display());
+            }
+            for path in &report.skipped {
+                println!("skipped existing {}", target.join(path).display());
+            }
+            for path in &report.unrestorable {
+                eprintln!("warning: {} was flattened as {}, converted or truncated and cannot be restored", path.display(), file_processing::NON_UTF8_PLACEHOLDER);
+            }
+            Ok(())
+        }
+    }
+}
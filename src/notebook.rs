@@ -0,0 +1,171 @@
+use std::path::Path;
+use serde_json::Value;
+use crate::formatter::fence_for;
+use crate::truncate::group_thousands;
+
+/// The header note and record field value marking a rendered notebook.
+pub const CONVERSION: &str = "notebook";
+
+/// The extension whose syntax describes a rendered notebook.
+pub const OUTPUT_EXTENSION: &str = "md";
+
+/// The kernel language assumed when a notebook does not name one.
+const DEFAULT_LANGUAGE: &str = "python";
+
+/// Returns `true` if `path` names a Jupyter notebook.
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ipynb"))
+}
+
+/// Renders a Jupyter notebook as Markdown.
+///
+/// Markdown and raw cells are written as they are, and code cells as fenced
+/// blocks labelled with the kernel's language from `metadata.kernelspec` or
+/// `metadata.language_info`. Cell outputs are dropped unless `output_lines`
+/// is given, in which case the text of each code cell's outputs is written in
+/// a following `text` block, cut to that many lines. Images and other rich
+/// outputs without a `text/plain` form are replaced with a note naming their
+/// MIME type, and errors with their exception name and message.
+///
+/// # Arguments
+///
+/// * `json` - The notebook file's content.
+/// * `output_lines` - The number of output lines kept per code cell, or `None` to drop outputs.
+///
+/// # Returns
+///
+/// * `Some(String)` with the rendered notebook.
+/// * `None` if `json` is not an nbformat 4 notebook, so the file is written as is.
+pub fn render(json: &str, output_lines: Option<usize>) -> Option<String> {
+    let notebook: Value = serde_json::from_str(json).ok()?;
+    let cells = notebook.get("cells")?.as_array()?;
+    let metadata = notebook.get("metadata");
+    let language = metadata
+        .and_then(|m| m.pointer("/kernelspec/language").or_else(|| m.pointer("/language_info/name")))
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_LANGUAGE)
+        .to_lowercase();
+
+    let mut blocks = Vec::with_capacity(cells.len());
+    for cell in cells {
+        let source = joined(cell.get("source")?);
+        match cell.get("cell_type")?.as_str()? {
+            _ if source.trim().is_empty() => {}
+            "code" => {
+                let fence = fence_for(&source);
+                let mut block = format!("{}{}\n{}\n{}", fence, language, source.trim_end_matches('\n'), fence);
+                if let Some(max_lines) = output_lines {
+                    let outputs = cell.get("outputs").and_then(Value::as_array).map(|outputs| outputs_text(outputs, max_lines));
+                    if let Some(text) = outputs.filter(|text| !text.is_empty()) {
+                        let fence = fence_for(&text);
+                        block.push_str(&format!("\n\n{}text\n{}\n{}", fence, text.trim_end_matches('\n'), fence));
+                    }
+                }
+                blocks.push(block);
+            }
+            _ => blocks.push(source.trim_end().to_string()),
+        }
+    }
+
+    let mut rendered = blocks.join("\n\n");
+    if !rendered.is_empty() {
+        rendered.push('\n');
+    }
+    Some(rendered)
+}
+
+/// Returns the text of a code cell's outputs, cut to `max_lines` lines.
+fn outputs_text(outputs: &[Value], max_lines: usize) -> String {
+    let mut text = String::new();
+    for output in outputs {
+        let part = match output.get("output_type").and_then(Value::as_str) {
+            Some("stream") => output.get("text").map(joined),
+            Some("execute_result") | Some("display_data") => output.get("data").map(|data| match data.get("text/plain") {
+                Some(plain) => joined(plain),
+                None => {
+                    let mimes: Vec<&str> = data.as_object().map(|d| d.keys().map(String::as_str).collect()).unwrap_or_default();
+                    format!("[{} output omitted]\n", mimes.join(", "))
+                }
+            }),
+            Some("error") => {
+                let field = |name: &str| output.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+                Some(format!("{}: {}\n", field("ename"), field("evalue")))
+            }
+            _ => None,
+        };
+        if let Some(part) = part {
+            text.push_str(&part);
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+    }
+
+    let line_count = text.lines().count();
+    if line_count <= max_lines {
+        return text;
+    }
+    let mut kept: String = text.split_inclusive('\n').take(max_lines).collect();
+    kept.push_str(&format!("… {} lines omitted …\n", group_thousands((line_count - max_lines) as u64)));
+    kept
+}
+
+/// Joins a multiline notebook string, stored either as one string or as a list of lines.
+fn joined(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+        "cells": [
+            {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "Loads the data."]},
+            {"cell_type": "code", "execution_count": 1, "metadata": {}, "source": ["import pandas as pd\n", "df = pd.read_csv('a.csv')\n", "df.head()"],
+             "outputs": [
+                {"output_type": "stream", "name": "stdout", "text": ["line 1\n", "line 2\n", "line 3\n"]},
+                {"output_type": "display_data", "data": {"image/png": "iVBORw0KGgo="}, "metadata": {}}
+             ]},
+            {"cell_type": "code", "execution_count": 2, "metadata": {}, "source": "1 / 0",
+             "outputs": [{"output_type": "error", "ename": "ZeroDivisionError", "evalue": "division by zero", "traceback": []}]}
+        ],
+        "metadata": {"kernelspec": {"name": "python3", "language": "python"}},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    }"##;
+
+    #[test]
+    fn test_render_drops_outputs() {
+        assert_eq!(
+            render(NOTEBOOK, None).unwrap(),
+            "# Analysis\nLoads the data.\n\n```python\nimport pandas as pd\ndf = pd.read_csv('a.csv')\ndf.head()\n```\n\n```python\n1 / 0\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_render_truncates_outputs() {
+        let rendered = render(NOTEBOOK, Some(2)).unwrap();
+        assert!(rendered.contains("df.head()\n```\n\n```text\nline 1\nline 2\n… 2 lines omitted …\n```\n"), "{}", rendered);
+        assert!(rendered.ends_with("1 / 0\n```\n\n```text\nZeroDivisionError: division by zero\n```\n"), "{}", rendered);
+
+        let rendered = render(NOTEBOOK, Some(10)).unwrap();
+        assert!(rendered.contains("line 3\n[image/png output omitted]\n```"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_render_rejects_other_json() {
+        assert_eq!(render("{\"name\": \"package\"}", None), None);
+        assert_eq!(render("not json", None), None);
+    }
+
+    #[test]
+    fn test_is_notebook() {
+        assert!(is_notebook(Path::new("analysis/Report.IPYNB")));
+        assert!(!is_notebook(Path::new("analysis.py")));
+    }
+}
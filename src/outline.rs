@@ -0,0 +1,453 @@
+use std::io;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Name scopes of declarations whose bodies are collapsed to `{ … }`.
+const FUNCTION_SCOPES: &[&str] = &["entity.name.function", "entity.name.macro"];
+
+/// Name scopes of declarations whose members are outlined in turn.
+const CONTAINER_SCOPES: &[&str] = &[
+    "entity.name.class",
+    "entity.name.struct",
+    "entity.name.enum",
+    "entity.name.union",
+    "entity.name.trait",
+    "entity.name.impl",
+    "entity.name.interface",
+    "entity.name.type",
+    "entity.name.module",
+    "entity.name.namespace",
+];
+
+/// Words that continue a signature on the next line, e.g. a Rust `where` clause.
+const CONTINUATIONS: &[&str] = &["{", "where", "->", "throws", ")", ":"];
+
+/// How a declaration is shown in the outline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Functions and macros: the signature is kept and the body collapsed.
+    Function,
+    /// Types, traits, impls and modules: the header is kept and members are
+    /// outlined, or the body is collapsed when it declares nothing.
+    Container,
+}
+
+/// A line with the punctuation and declaration name syntect found in it.
+struct TokenizedLine<'a> {
+    /// The line without its line ending.
+    body: &'a str,
+    /// The line ending, `\n`, `\r\n` or empty for a final unterminated line.
+    ending: &'a str,
+    /// Brackets, `;` and `:` outside strings and comments, with their offsets.
+    punctuation: Vec<(usize, char)>,
+    /// The end of the last non-whitespace character outside strings and comments.
+    code_end: usize,
+    /// The kind of the first declaration name on the line and where that name ends.
+    declaration: Option<(Kind, usize)>,
+}
+
+/// A signature that continues past the line it started on.
+struct Signature {
+    kind: Kind,
+    /// The indentation of the line holding the name.
+    indent: usize,
+    /// Open `(` and `[` brackets.
+    brackets: usize,
+    /// Whether a `where` clause has started.
+    in_where: bool,
+}
+
+/// A line of the outline.
+struct OutlineLine<'a> {
+    text: String,
+    ending: &'a str,
+    number: Option<usize>,
+}
+
+/// Reduces `text` to its declarations, using the `entity.name.*` scopes syntect
+/// assigns while tokenizing it as `syntax`, so every language syntect ships is
+/// covered without per-language rules.
+///
+/// Function signatures are kept with their bodies replaced by `{ … }`, or by
+/// `…` after a trailing `:` as in Python. Types, traits, impls and modules keep
+/// their header line and their nested declarations, followed by the closing
+/// line; one that declares nothing inside is collapsed to `{ … }` like a
+/// function. Signatures spanning several lines are kept whole. All other lines
+/// are dropped, and the remaining ones stay in source order.
+///
+/// Lines whose number in `numbers` is `None`, such as the elision marker of a
+/// truncated file, are kept untouched and separate independently tokenized
+/// stretches of text.
+///
+/// # Arguments
+///
+/// * `text` - The content to outline.
+/// * `numbers` - The original line number of every line of `text`.
+/// * `syntax` - The syntax to tokenize `text` with.
+/// * `syntax_set` - The syntax set `syntax` belongs to.
+///
+/// # Returns
+///
+/// The outline and the original line numbers of its lines.
+///
+/// # Errors
+///
+/// Returns an error if syntect fails to tokenize a line.
+pub fn outline(
+    text: &str,
+    numbers: &[Option<usize>],
+    syntax: &SyntaxReference,
+    syntax_set: &SyntaxSet,
+) -> io::Result<(String, Vec<Option<usize>>)> {
+    let lines: Vec<&str> = LinesWithEndings::from(text).collect();
+    let mut outline = Vec::new();
+
+    // Markers split the text into stretches that are tokenized from a fresh state
+    let mut start = 0;
+    while start < lines.len() {
+        if numbers.get(start).copied().flatten().is_none() {
+            let body = lines[start].trim_end_matches(['\n', '\r']);
+            outline.push(OutlineLine { text: body.to_string(), ending: &lines[start][body.len()..], number: None });
+            start += 1;
+            continue;
+        }
+        let end = (start..lines.len()).find(|&i| numbers.get(i).copied().flatten().is_none()).unwrap_or(lines.len());
+        let tokenized = tokenize(&lines[start..end], syntax, syntax_set)?;
+        let segment_numbers: Vec<Option<usize>> = (start..end).map(|i| numbers.get(i).copied().flatten()).collect();
+        outline_segment(&tokenized, &segment_numbers, &mut outline);
+        start = end;
+    }
+
+    let mut text = String::with_capacity(outline.iter().map(|line| line.text.len() + 1).sum());
+    for line in &outline {
+        text.push_str(&line.text);
+        text.push_str(line.ending);
+    }
+    Ok((text, outline.into_iter().map(|line| line.number).collect()))
+}
+
+/// Appends the declarations of one stretch of tokenized lines to `outline`.
+fn outline_segment<'a>(lines: &[TokenizedLine<'a>], numbers: &[Option<usize>], outline: &mut Vec<OutlineLine<'a>>) {
+    let mut depth = 0;
+    // Open containers: the depth outside their braces, the outline line and the offset of the `{`
+    let mut containers: Vec<(usize, usize, usize)> = Vec::new();
+    // The depth at which a collapsed body ends
+    let mut skip_to: Option<usize> = None;
+    // The indentation that lines of a collapsed `:` body are deeper than
+    let mut skip_indented: Option<usize> = None;
+    let mut pending: Option<Signature> = None;
+
+    for (line, &number) in lines.iter().zip(numbers) {
+        let indent = line.body.len() - line.body.trim_start().len();
+        let trimmed = line.body.trim();
+
+        if skip_indented.is_some_and(|base| trimmed.is_empty() || indent > base) {
+            depth = track_braces(&line.punctuation, depth, None).0;
+            continue;
+        }
+        skip_indented = None;
+
+        // The rest of a collapsed body, up to and including its closing brace
+        if let Some(target) = skip_to {
+            let (new_depth, closed) = track_braces(&line.punctuation, depth, Some(target));
+            depth = new_depth;
+            if closed {
+                skip_to = None;
+            }
+            continue;
+        }
+
+        // A pending signature continues on brackets, `where` clauses and similar
+        let continues = pending.as_ref().is_some_and(|signature| {
+            signature.brackets > 0
+                || (signature.in_where && !trimmed.is_empty() && line.declaration.is_none())
+                || CONTINUATIONS.iter().any(|word| trimmed.starts_with(word))
+        });
+        let signature = match (pending.take(), line.declaration) {
+            (Some(mut signature), _) if continues => {
+                signature.in_where |= trimmed.starts_with("where");
+                Some((signature, 0))
+            }
+            (_, Some((kind, name_end))) => Some((Signature { kind, indent, brackets: 0, in_where: false }, name_end)),
+            _ => None,
+        };
+
+        let Some((mut signature, name_end)) = signature else {
+            // Lines that close a container are kept, unless it declared nothing
+            let closed = close_containers(&line.punctuation, &mut depth, &mut containers, outline);
+            if closed {
+                outline.push(OutlineLine { text: line.body.to_string(), ending: line.ending, number });
+            }
+            continue;
+        };
+
+        // Braces before the name belong to the surrounding code
+        let (before, after): (Vec<_>, Vec<_>) = line.punctuation.iter().partition(|(offset, _)| *offset < name_end);
+        close_containers(&before, &mut depth, &mut containers, outline);
+
+        let mut resolved = false;
+        for (position, &(offset, c)) in after.iter().enumerate() {
+            match c {
+                '(' | '[' => signature.brackets += 1,
+                ')' | ']' => signature.brackets = signature.brackets.saturating_sub(1),
+                ';' if signature.brackets == 0 => {
+                    outline.push(OutlineLine { text: line.body.to_string(), ending: line.ending, number });
+                    depth = track_braces(&after[position + 1..], depth, None).0;
+                    resolved = true;
+                    break;
+                }
+                '{' if signature.brackets == 0 => {
+                    let outside = depth;
+                    let (new_depth, closed) = track_braces(&after[position + 1..], outside + 1, Some(outside));
+                    depth = new_depth;
+                    match signature.kind {
+                        Kind::Function => {
+                            let head = line.body[..offset].trim_end();
+                            let text = if head.is_empty() { format!("{}{{ … }}", &line.body[..indent]) } else { format!("{} {{ … }}", head) };
+                            outline.push(OutlineLine { text, ending: line.ending, number });
+                            if !closed {
+                                skip_to = Some(outside);
+                            }
+                        }
+                        Kind::Container => {
+                            outline.push(OutlineLine { text: line.body.to_string(), ending: line.ending, number });
+                            if !closed {
+                                containers.push((outside, outline.len() - 1, offset));
+                            }
+                        }
+                    }
+                    resolved = true;
+                    break;
+                }
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        if resolved {
+            continue;
+        }
+
+        outline.push(OutlineLine { text: line.body.to_string(), ending: line.ending, number });
+        let ends_with_colon = line.body[..line.code_end].ends_with(':');
+        if ends_with_colon && signature.brackets == 0 {
+            // A Python-style body is the block indented deeper than the signature
+            if signature.kind == Kind::Function {
+                if let Some(last) = outline.last_mut() {
+                    last.text = format!("{} …", &line.body[..line.code_end]);
+                }
+                skip_indented = Some(signature.indent);
+            }
+        } else {
+            pending = Some(signature);
+        }
+    }
+}
+
+/// Applies the braces in `punctuation` to `depth`.
+///
+/// # Returns
+///
+/// The new depth, and whether a closing brace brought the depth down to `target`.
+fn track_braces(punctuation: &[(usize, char)], mut depth: usize, target: Option<usize>) -> (usize, bool) {
+    let mut reached = false;
+    for &(_, c) in punctuation {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.saturating_sub(1);
+                reached |= target == Some(depth);
+            }
+            _ => {}
+        }
+    }
+    (depth, reached)
+}
+
+/// Applies the braces in `punctuation` to `depth`, closing containers they end.
+///
+/// A container that declared nothing is collapsed to `{ … }` on its header line.
+///
+/// # Returns
+///
+/// `true` if a container with nested declarations was closed, so the line
+/// holding its closing brace belongs in the outline.
+fn close_containers(
+    punctuation: &[(usize, char)],
+    depth: &mut usize,
+    containers: &mut Vec<(usize, usize, usize)>,
+    outline: &mut [OutlineLine],
+) -> bool {
+    let mut keep_line = false;
+    for &(_, c) in punctuation {
+        match c {
+            '{' => *depth += 1,
+            '}' => {
+                *depth = depth.saturating_sub(1);
+                if let Some(&(_, index, brace)) = containers.last().filter(|(outside, _, _)| *outside == *depth) {
+                    containers.pop();
+                    if index + 1 == outline.len() {
+                        let header = &mut outline[index].text;
+                        header.truncate(brace + 1);
+                        header.push_str(" … }");
+                    } else {
+                        keep_line = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    keep_line
+}
+
+/// Tokenizes `lines` and records the punctuation and declaration names of each one.
+fn tokenize<'a>(lines: &[&'a str], syntax: &SyntaxReference, syntax_set: &SyntaxSet) -> io::Result<Vec<TokenizedLine<'a>>> {
+    let scopes = |names: &[&str]| names.iter().map(|name| Scope::new(name).map_err(io::Error::other)).collect::<io::Result<Vec<_>>>();
+    let functions = scopes(FUNCTION_SCOPES)?;
+    let containers = scopes(CONTAINER_SCOPES)?;
+    let non_code = scopes(&["comment", "string"])?;
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut tokenized = Vec::with_capacity(lines.len());
+
+    for line in lines {
+        let body_len = line.trim_end_matches(['\n', '\r']).len();
+        let ops = state.parse_line(line, syntax_set).map_err(io::Error::other)?;
+        let mut punctuation = Vec::new();
+        let mut code_end = 0;
+        let mut declaration = None;
+        let mut position = 0;
+
+        for (offset, op) in ops.iter().map(|(offset, op)| (*offset, Some(op))).chain([(line.len(), None)]) {
+            let segment_end = offset.min(body_len);
+            if position < segment_end {
+                let has = |prefixes: &[Scope]| stack.as_slice().iter().any(|scope| prefixes.iter().any(|p| p.is_prefix_of(*scope)));
+                if declaration.is_none() {
+                    if has(&functions) {
+                        declaration = Some((Kind::Function, segment_end));
+                    } else if has(&containers) {
+                        declaration = Some((Kind::Container, segment_end));
+                    }
+                }
+                if !has(&non_code) {
+                    for (index, c) in line[position..segment_end].char_indices() {
+                        if matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | ';' | ':') {
+                            punctuation.push((position + index, c));
+                        }
+                        if !c.is_whitespace() {
+                            code_end = position + index + c.len_utf8();
+                        }
+                    }
+                }
+            }
+            position = position.max(segment_end);
+
+            if let Some(op) = op {
+                stack.apply(op).map_err(io::Error::other)?;
+            }
+        }
+
+        tokenized.push(TokenizedLine { body: &line[..body_len], ending: &line[body_len..], punctuation, code_end, declaration });
+    }
+
+    Ok(tokenized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Outlines `text` as a file with `extension`, numbering its lines from 1.
+    fn outline_of(text: &str, extension: &str) -> (String, Vec<Option<usize>>) {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let syntax = ss.find_syntax_by_extension(extension).unwrap();
+        let numbers: Vec<Option<usize>> = (1..=text.lines().count()).map(Some).collect();
+        outline(text, &numbers, syntax, &ss).unwrap()
+    }
+
+    #[test]
+    fn test_outline_rust() {
+        let source = "\
+use std::io;
+
+/// A point.
+pub struct Point {
+    x: i32,
+}
+
+impl Point {
+    pub fn new(x: i32) -> Self {
+        Point { x }
+    }
+
+    fn long<T>(
+        a: T,
+    ) -> T
+    where
+        T: Clone,
+    {
+        a
+    }
+}
+
+trait Shape { fn area(&self) -> f64; }
+";
+        let (outlined, numbers) = outline_of(source, "rs");
+        assert_eq!(
+            outlined,
+            "\
+pub struct Point { … }
+impl Point {
+    pub fn new(x: i32) -> Self { … }
+    fn long<T>(
+        a: T,
+    ) -> T
+    where
+        T: Clone,
+    { … }
+}
+trait Shape { fn area(&self) -> f64; }
+"
+        );
+        assert_eq!(numbers, [4, 8, 9, 13, 14, 15, 16, 17, 18, 21, 23].map(Some).to_vec());
+    }
+
+    #[test]
+    fn test_outline_python() {
+        let source = "\
+import os
+
+class Shape(Base):
+    \"\"\"A shape.\"\"\"
+
+    def area(self,
+             scale: float) -> float:
+        if scale:
+            return 1.0
+        return 0.0
+
+def main(): pass
+";
+        let (outlined, _) = outline_of(source, "py");
+        assert_eq!(outlined, "class Shape(Base):\n    def area(self,\n             scale: float) -> float: …\ndef main(): pass\n");
+    }
+
+    #[test]
+    fn test_outline_ignores_braces_in_strings_and_comments() {
+        let source = "function f() {\n  const s = \"}\"; // }\n  return s;\n}\nfunction g() {}\n";
+        assert_eq!(outline_of(source, "js").0, "function f() { … }\nfunction g() { … }\n");
+    }
+
+    #[test]
+    fn test_outline_keeps_markers() {
+        let ss = SyntaxSet::load_defaults_newlines();
+        let syntax = ss.find_syntax_by_extension("rs").unwrap();
+        let text = "fn a() {\n… 5 lines omitted …\nfn z() {}\n";
+        let numbers = vec![Some(1), None, Some(8)];
+        let (outlined, kept) = outline(text, &numbers, syntax, &ss).unwrap();
+        assert_eq!(outlined, "fn a() { … }\n… 5 lines omitted …\nfn z() { … }\n");
+        assert_eq!(kept, vec![Some(1), None, Some(8)]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+use crate::formatter::{FileInfo, OutputFormatter};
+use crate::types::ContentEncoding;
+
+/// The all-zero object name git uses for the missing side of a new file.
+const NULL_OBJECT: &str = "0000000000000000000000000000000000000000";
+
+/// The alphabet of git's base85 encoding used in `GIT binary patch` hunks.
+const BASE85_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Writes the selected files as a git patch that creates each one from `/dev/null`.
+///
+/// Text files become ordinary unified-diff hunks, while non-UTF-8 files and
+/// text containing NUL bytes become `GIT binary patch` literals, so the whole
+/// output can be applied to an empty repository with `git apply`. Executable
+/// files are created with mode `100755`, all others with `100644`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PatchFormatter;
+
+impl OutputFormatter for PatchFormatter {
+    fn encode_binary(&self) -> bool {
+        true
+    }
+
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        let path = file.path.display().to_string().replace('\\', "/");
+        writeln!(output, "diff --git {} {}", quote_path("a/", &path), quote_path("b/", &path))?;
+        writeln!(output, "new file mode {}", if file.executable { "100755" } else { "100644" })
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        let path = file.path.display().to_string().replace('\\', "/");
+        let bytes = match file.encoding {
+            ContentEncoding::Base64 => BASE64.decode(content).map_err(io::Error::other)?,
+            _ => content.as_bytes().to_vec(),
+        };
+        writeln!(output, "index {}..{}", NULL_OBJECT, blob_id(&bytes))?;
+
+        // Empty files carry no hunk at all
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        if file.encoding == ContentEncoding::Base64 || bytes.contains(&0) {
+            return write_binary_hunk(output, &bytes);
+        }
+
+        writeln!(output, "--- /dev/null")?;
+        writeln!(output, "+++ {}", quote_path("b/", &path))?;
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        if lines.len() == 1 {
+            writeln!(output, "@@ -0,0 +1 @@")?;
+        } else {
+            writeln!(output, "@@ -0,0 +1,{} @@", lines.len())?;
+        }
+        for line in lines {
+            write!(output, "+{}", line)?;
+            if !line.ends_with('\n') {
+                writeln!(output)?;
+                writeln!(output, "\\ No newline at end of file")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a `GIT binary patch` hunk creating `bytes` from nothing.
+///
+/// The forward literal holds the zlib-compressed content in git's base85
+/// encoding, and the reverse literal is the empty blob, as `git diff --binary`
+/// would emit.
+fn write_binary_hunk(output: &mut dyn Write, bytes: &[u8]) -> io::Result<()> {
+    writeln!(output, "GIT binary patch")?;
+    writeln!(output, "literal {}", bytes.len())?;
+    write_base85_lines(output, &deflate(bytes)?)?;
+    writeln!(output)?;
+    writeln!(output, "literal 0")?;
+    write_base85_lines(output, &deflate(&[])?)?;
+    writeln!(output)
+}
+
+/// Compresses `bytes` with zlib, as git does for binary literals.
+fn deflate(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Writes `data` as git base85 lines of at most 52 input bytes each.
+///
+/// Every line starts with a length character, `A`-`Z` for 1-26 bytes and
+/// `a`-`z` for 27-52 bytes, followed by five characters per four-byte group.
+fn write_base85_lines(output: &mut dyn Write, data: &[u8]) -> io::Result<()> {
+    for chunk in data.chunks(52) {
+        let length = chunk.len() as u8;
+        let prefix = if length <= 26 { b'A' + length - 1 } else { b'a' + length - 27 };
+        let mut line = vec![prefix];
+        for group in chunk.chunks(4) {
+            let mut padded = [0u8; 4];
+            padded[..group.len()].copy_from_slice(group);
+            let mut value = u32::from_be_bytes(padded);
+            let mut encoded = [0u8; 5];
+            for slot in encoded.iter_mut().rev() {
+                *slot = BASE85_ALPHABET[(value % 85) as usize];
+                value /= 85;
+            }
+            line.extend_from_slice(&encoded);
+        }
+        output.write_all(&line)?;
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+/// Returns the git blob object name of `bytes`.
+fn blob_id(bytes: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", bytes.len()).as_bytes());
+    hasher.update(bytes);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Prefixes a path for a diff header, C-quoting it the way git does when it
+/// contains quotes, backslashes or control characters.
+fn quote_path(prefix: &str, path: &str) -> String {
+    if !path.chars().any(|c| c == '"' || c == '\\' || c.is_control()) {
+        return format!("{}{}", prefix, path);
+    }
+
+    let mut quoted = format!("\"{}", prefix);
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\t' => quoted.push_str("\\t"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_id() {
+        // Object names match `git hash-object`
+        assert_eq!(blob_id(b""), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        assert_eq!(blob_id(b"a\nb"), "0a207c060e61f3b88eaee0a8cd0696f46fb155eb");
+    }
+
+    #[test]
+    fn test_base85_lines() {
+        let mut output = Vec::new();
+        write_base85_lines(&mut output, &[0, 0, 0, 0]).unwrap();
+        assert_eq!(output, b"D00000\n");
+
+        // Lines hold at most 52 bytes, with lowercase length prefixes above 26
+        let mut output = Vec::new();
+        write_base85_lines(&mut output, &[0; 60]).unwrap();
+        let lines: Vec<&[u8]> = output.split(|b| *b == b'\n').filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][0], b'z');
+        assert_eq!(lines[0].len(), 1 + 13 * 5);
+        assert_eq!(lines[1][0], b'H');
+    }
+
+    #[test]
+    fn test_quote_path() {
+        assert_eq!(quote_path("a/", "src/main.rs"), "a/src/main.rs");
+        assert_eq!(quote_path("b/", "with space.txt"), "b/with space.txt");
+        assert_eq!(quote_path("a/", "tab\there\"q\""), "\"a/tab\\there\\\"q\\\"\"");
+    }
+}
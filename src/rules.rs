@@ -0,0 +1,313 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use globset::{GlobBuilder, GlobMatcher};
+use path_clean::PathClean;
+use regex::Regex;
+use crate::types::{ExcludeList, IncludeList};
+
+/// The prefix marking a pattern as a regular expression.
+pub const REGEX_PREFIX: &str = "re:";
+
+/// The characters that make a pattern a glob rather than a literal path.
+const GLOB_CHARS: &[char] = &['*', '?', '[', '{'];
+
+/// How a rule's pattern is matched against paths.
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// A file or directory, relative to the base directory.
+    Literal(PathBuf),
+    /// A glob in gitignore style, anchored to the base directory if it contains a `/`.
+    Glob(GlobMatcher),
+    /// A regular expression searched for in the `/`-separated relative path.
+    Regex(Regex),
+}
+
+/// One include or exclude pattern, in the order it was given.
+///
+/// A rule matches a path if its pattern matches the path itself or one of its
+/// parent directories, so a rule naming a directory covers everything in it.
+/// Clones share the record of whether the rule has matched anything.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// The pattern as it was written.
+    pub pattern: String,
+    /// The rule's place among all include and exclude rules. Where several
+    /// rules match a path, the one with the highest position decides.
+    pub position: usize,
+    matcher: Matcher,
+    matched: Arc<AtomicBool>,
+    builtin: bool,
+}
+
+impl Rule {
+    /// Parses a pattern into a rule.
+    ///
+    /// Patterns starting with `re:` are regular expressions, searched for in
+    /// the path relative to `base_dir` with `/` separators, so `re:\.lock$`
+    /// matches every lock file. Patterns containing `*`, `?`, `[` or `{` are
+    /// globs in gitignore style: `*` stays within one path segment, `**`
+    /// crosses any number, and a glob without a `/` matches at any depth while
+    /// a leading `/` anchors one to `base_dir`. A pattern naming a path that
+    /// exists under `base_dir` is taken literally even if it contains those
+    /// characters, so files such as `src/[id].tsx` or `pages/{slug}.vue` match
+    /// themselves. Anything else is a literal path, relative to `base_dir`
+    /// unless it is absolute.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_dir` - The canonical directory being flattened.
+    /// * `pattern` - The pattern to parse.
+    /// * `position` - The rule's place among all include and exclude rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if the glob or regular expression is
+    /// malformed, or if a literal path lies outside `base_dir`.
+    pub fn new(base_dir: &Path, pattern: &Path, position: usize) -> io::Result<Self> {
+        let text = pattern.to_string_lossy().into_owned();
+        let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid pattern '{}': {}", text, reason));
+
+        let matcher = match pattern.to_str() {
+            Some(regex) if regex.starts_with(REGEX_PREFIX) => {
+                Matcher::Regex(Regex::new(&regex[REGEX_PREFIX.len()..]).map_err(|e| invalid(e.to_string()))?)
+            }
+            Some(glob) if glob.contains(GLOB_CHARS) && !base_dir.join(pattern).exists() => {
+                let trimmed = glob.trim_end_matches('/');
+                let anchored = match trimmed.strip_prefix('/') {
+                    Some(anchored) => anchored.to_string(),
+                    None if trimmed.contains('/') => trimmed.to_string(),
+                    None => format!("**/{}", trimmed),
+                };
+                let glob = GlobBuilder::new(&anchored).literal_separator(true).build().map_err(|e| invalid(e.kind().to_string()))?;
+                Matcher::Glob(glob.compile_matcher())
+            }
+            _ => {
+                let relative = relative_to(base_dir, pattern).ok_or_else(|| invalid(format!("outside {}", base_dir.display())))?;
+                Matcher::Literal(relative)
+            }
+        };
+        Ok(Rule { pattern: text, position, matcher, matched: Arc::new(AtomicBool::new(false)), builtin: false })
+    }
+
+    /// Returns `true` if the rule matches `rel_path` or one of its parent directories.
+    ///
+    /// # Arguments
+    ///
+    /// * `rel_path` - A path relative to the base directory.
+    pub fn matches(&self, rel_path: &Path) -> bool {
+        let matched = match &self.matcher {
+            Matcher::Literal(literal) => rel_path.starts_with(literal),
+            Matcher::Glob(glob) => rel_path.ancestors().any(|path| !path.as_os_str().is_empty() && glob.is_match(path)),
+            Matcher::Regex(regex) => rel_path
+                .ancestors()
+                .any(|path| !path.as_os_str().is_empty() && regex.is_match(&slash_separated(path))),
+        };
+        if matched {
+            self.matched.store(true, Ordering::Relaxed);
+        }
+        matched
+    }
+
+    /// Returns `true` if the rule has matched any path so far.
+    pub fn has_matched(&self) -> bool {
+        self.matched.load(Ordering::Relaxed)
+    }
+
+    /// Returns the path a literal rule names, relative to the base directory.
+    pub fn literal_path(&self) -> Option<&Path> {
+        match &self.matcher {
+            Matcher::Literal(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the kind of pattern: `literal`, `glob` or `regex`.
+    pub fn kind(&self) -> &'static str {
+        match self.matcher {
+            Matcher::Literal(_) => "literal",
+            Matcher::Glob(_) => "glob",
+            Matcher::Regex(_) => "regex",
+        }
+    }
+
+    /// Returns `true` if the rule is built in rather than given by the user.
+    pub fn is_builtin(&self) -> bool {
+        self.builtin
+    }
+
+    /// Marks the rule as built in, so it is never reported as unused.
+    pub(crate) fn into_builtin(self) -> Self {
+        self.matched.store(true, Ordering::Relaxed);
+        Rule { builtin: true, ..self }
+    }
+}
+
+/// The outcome of checking a path against the include and exclude rules.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision<'a> {
+    /// Whether the path is selected.
+    pub included: bool,
+    /// The rule that decided, or `None` if no rule matched.
+    pub rule: Option<&'a Rule>,
+}
+
+/// Decides whether a path is selected by the include and exclude rules.
+///
+/// Every rule is checked and the matching rule with the highest position
+/// wins, so a later `--include` can bring back a file inside an excluded
+/// directory and a later `--exclude` can drop part of an included one. An
+/// exclude wins over an include at the same position. A path no rule matches
+/// is selected only if there are no include rules.
+///
+/// # Arguments
+///
+/// * `rel_path` - A path relative to the base directory.
+/// * `exclude` - The exclude rules.
+/// * `include` - The include rules.
+pub fn decide<'a>(rel_path: &Path, exclude: &'a ExcludeList, include: &'a IncludeList) -> Decision<'a> {
+    // Every rule is tested, rather than stopping at the first, so that unused rules can be reported
+    let last_match = |rules: &'a [Rule]| rules.iter().filter(|rule| rule.matches(rel_path)).max_by_key(|rule| rule.position);
+    match (last_match(&exclude.0), last_match(&include.0)) {
+        (Some(excluded), Some(included)) if included.position > excluded.position => Decision { included: true, rule: Some(included) },
+        (Some(excluded), _) => Decision { included: false, rule: Some(excluded) },
+        (None, Some(included)) => Decision { included: true, rule: Some(included) },
+        (None, None) => Decision { included: include.0.is_empty(), rule: None },
+    }
+}
+
+/// Returns `true` if nothing inside directory `rel_path` can be selected, so
+/// the walk need not enter it.
+///
+/// A directory is skipped when an exclude rule decides it and no include rule
+/// comes after that exclude, since only a later include could select a file inside.
+pub fn skips_directory(rel_path: &Path, exclude: &ExcludeList, include: &IncludeList) -> bool {
+    let decision = decide(rel_path, exclude, include);
+    match decision.rule {
+        Some(rule) if !decision.included => include.0.iter().all(|later| later.position < rule.position),
+        _ => false,
+    }
+}
+
+/// Resolves a literal path against `base_dir` without touching the filesystem,
+/// except to see through symlinks in absolute paths.
+///
+/// # Returns
+///
+/// * `Some(PathBuf)` with the normalized path relative to `base_dir`.
+/// * `None` if the path lies outside `base_dir`.
+pub fn relative_to(base_dir: &Path, path: &Path) -> Option<PathBuf> {
+    let full_path = base_dir.join(path).clean();
+    match full_path.strip_prefix(base_dir) {
+        Ok(relative) => Some(relative.to_path_buf()),
+        // An absolute path may reach the directory through a symlink
+        Err(_) => full_path.canonicalize().ok()?.strip_prefix(base_dir).ok().map(Path::to_path_buf),
+    }
+}
+
+/// Returns `path` as a string with `/` separators on every platform.
+fn slash_separated(path: &Path) -> String {
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, position: usize) -> Rule {
+        Rule::new(Path::new("/project"), Path::new(pattern), position).unwrap()
+    }
+
+    #[test]
+    fn test_rule_kinds() {
+        let lock = rule("*.lock", 1);
+        assert_eq!(lock.kind(), "glob");
+        assert!(lock.matches(Path::new("Cargo.lock")));
+        assert!(lock.matches(Path::new("web/yarn.lock")));
+        assert!(!lock.matches(Path::new("Cargo.lock.md")));
+
+        let sources = rule("src/**/*.rs", 1);
+        assert!(sources.matches(Path::new("src/main.rs")));
+        assert!(sources.matches(Path::new("src/a/b.rs")));
+        assert!(!sources.matches(Path::new("tests/src/main.rs")));
+
+        let generated = rule("re:_generated\\.(rs|go)$", 1);
+        assert_eq!(generated.kind(), "regex");
+        assert!(generated.matches(Path::new("api/schema_generated.go")));
+        assert!(!generated.matches(Path::new("api/schema.go")));
+
+        let target = rule("./target/", 1);
+        assert_eq!(target.literal_path(), Some(Path::new("target")));
+        assert!(target.matches(Path::new("target/debug/flatten")));
+        assert!(!target.matches(Path::new("targets.txt")));
+        assert_eq!(rule("/project/docs", 1).literal_path(), Some(Path::new("docs")));
+    }
+
+    #[test]
+    fn test_existing_paths_with_glob_characters_are_literal() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let base = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(base.join("src")).unwrap();
+        std::fs::create_dir_all(base.join("pages")).unwrap();
+        std::fs::write(base.join("src").join("[id].tsx"), "").unwrap();
+        std::fs::write(base.join("pages").join("{slug}.vue"), "").unwrap();
+
+        for pattern in ["src/[id].tsx", "pages/{slug}.vue"] {
+            let literal = Rule::new(&base, Path::new(pattern), 1).unwrap();
+            assert_eq!(literal.kind(), "literal", "{}", pattern);
+            assert!(literal.matches(Path::new(pattern)), "{}", pattern);
+        }
+        // Patterns naming nothing that exists are still globs
+        let glob = Rule::new(&base, Path::new("src/[ab].tsx"), 1).unwrap();
+        assert_eq!(glob.kind(), "glob");
+        assert!(glob.matches(Path::new("src/a.tsx")));
+    }
+
+    #[test]
+    fn test_directory_globs_cover_contents() {
+        let vendor = rule("vendor*", 1);
+        assert!(vendor.matches(Path::new("lib/vendored/x.c")));
+        // A leading `/` anchors a glob to the base directory
+        assert!(rule("/*.md", 1).matches(Path::new("README.md")));
+        assert!(!rule("/*.md", 1).matches(Path::new("docs/guide.md")));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        for pattern in ["re:(unclosed", "src/[a-", "../outside"] {
+            let err = Rule::new(Path::new("/project"), Path::new(pattern), 1).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{}", pattern);
+            assert!(err.to_string().contains(pattern), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_decide_last_match_wins() {
+        let exclude = ExcludeList(vec![rule("vendor", 1), rule("*.md", 4)]);
+        let include = IncludeList(vec![rule("vendor/keep", 2), rule("src", 3)]);
+
+        let decision = decide(Path::new("vendor/keep/a.rs"), &exclude, &include);
+        assert!(decision.included);
+        assert_eq!(decision.rule.unwrap().pattern, "vendor/keep");
+        assert!(!decide(Path::new("vendor/other.rs"), &exclude, &include).included);
+        assert!(!decide(Path::new("src/README.md"), &exclude, &include).included);
+        assert!(decide(Path::new("src/main.rs"), &exclude, &include).included);
+        // With includes, unmatched paths are left out
+        let decision = decide(Path::new("Cargo.toml"), &exclude, &include);
+        assert!(!decision.included && decision.rule.is_none());
+        assert!(decide(Path::new("Cargo.toml"), &exclude, &IncludeList(vec![])).included);
+
+        assert!(!skips_directory(Path::new("vendor"), &exclude, &include));
+        assert!(skips_directory(Path::new("docs.md"), &exclude, &include));
+        assert!(exclude.0[0].has_matched());
+        assert!(!rule("unused", 5).has_matched());
+    }
+}
@@ -0,0 +1,419 @@
+use std::io::Read;
+use std::path::Path;
+use serde_json::{Map, Number, Value};
+use yaml_rust2::{Yaml, YamlLoader};
+use crate::truncate::group_thousands;
+use crate::types::SummaryOptions;
+
+/// The header note and record field value marking a summarized data file.
+pub const CONVERSION: &str = "summary";
+
+/// The extension whose syntax describes a summary.
+pub const OUTPUT_EXTENSION: &str = "txt";
+
+/// Sample values longer than this many characters are cut with `…`.
+const MAX_SAMPLE_CHARS: usize = 40;
+
+/// Objects with more distinct keys than this are summarized by their first keys only.
+const MAX_FIELDS: usize = 40;
+
+/// Paths wider than this are not padded to align the type column.
+const MAX_PATH_WIDTH: usize = 40;
+
+/// The formats a data file can be summarized from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Csv,
+    Tsv,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    /// Returns the data format named by the extension of `path`, if any.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "csv" => Some(DataFormat::Csv),
+            "tsv" => Some(DataFormat::Tsv),
+            "json" => Some(DataFormat::Json),
+            "yaml" | "yml" => Some(DataFormat::Yaml),
+            "toml" => Some(DataFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Returns the name used in the summary header, e.g. `CSV`.
+    fn name(self) -> &'static str {
+        match self {
+            DataFormat::Csv => "CSV",
+            DataFormat::Tsv => "TSV",
+            DataFormat::Json => "JSON",
+            DataFormat::Yaml => "YAML",
+            DataFormat::Toml => "TOML",
+        }
+    }
+}
+
+/// Summarizes a data file instead of writing it whole.
+///
+/// CSV and TSV files are streamed: the summary lists every column with its
+/// inferred type (`integer`, `float`, `boolean`, `string`, or a union such as
+/// `integer | empty`) and sample values, followed by the header and first
+/// `options.rows` rows. JSON, YAML and TOML files are parsed and their
+/// inferred schema is listed one path per line, e.g. `$.items[].price`, with
+/// the types found there and sample values cut to a few dozen characters.
+///
+/// # Arguments
+///
+/// * `reader` - The file content.
+/// * `format` - The format to parse it as.
+/// * `size` - The file size in bytes, for the summary header.
+/// * `options` - How many rows and sample values to keep.
+///
+/// # Returns
+///
+/// * `Some(String)` with the summary.
+/// * `None` if the content cannot be read or parsed as `format`, so the file
+///   is written as is.
+pub fn summarize<R: Read>(mut reader: R, format: DataFormat, size: u64, options: &SummaryOptions) -> Option<String> {
+    let header = format!("# {} summary of {} bytes", format.name(), group_thousands(size));
+    match format {
+        DataFormat::Csv => summarize_delimited(reader, b',', &header, options),
+        DataFormat::Tsv => summarize_delimited(reader, b'\t', &header, options),
+        DataFormat::Json | DataFormat::Yaml | DataFormat::Toml => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).ok()?;
+            let value = match format {
+                DataFormat::Json => serde_json::from_str(&text).ok()?,
+                DataFormat::Yaml => yaml_to_json(YamlLoader::load_from_str(&text).ok()?),
+                _ => serde_json::to_value(toml::from_str::<toml::Value>(&text).ok()?).ok()?,
+            };
+
+            let mut shape = Shape::default();
+            shape.add(&value, options.samples);
+            let mut rows = Vec::new();
+            shape.rows("$".to_string(), &mut rows);
+            Some(format!("{}\n{}", header, aligned(&rows)))
+        }
+    }
+}
+
+/// Summarizes CSV or TSV content separated by `delimiter`.
+fn summarize_delimited<R: Read>(reader: R, delimiter: u8, header: &str, options: &SummaryOptions) -> Option<String> {
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(reader);
+    let headers = reader.headers().ok()?.clone();
+    let mut columns: Vec<Column> = headers.iter().map(|_| Column::default()).collect();
+    let mut sample = csv::WriterBuilder::new().delimiter(delimiter).flexible(true).from_writer(Vec::new());
+    sample.write_record(&headers).ok()?;
+
+    let mut row_count: u64 = 0;
+    for record in reader.records() {
+        let record = record.ok()?;
+        if (row_count as usize) < options.rows {
+            sample.write_record(&record).ok()?;
+        }
+        row_count += 1;
+        for (index, field) in record.iter().enumerate() {
+            if index >= columns.len() {
+                columns.resize_with(index + 1, Column::default);
+            }
+            columns[index].add(field, options.samples);
+        }
+    }
+
+    let rows: Vec<[String; 3]> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            let name = headers.get(index).map_or_else(|| format!("(column {})", index + 1), String::from);
+            [name, column.kinds.join(" | "), column.samples.join(", ")]
+        })
+        .collect();
+    let sample = String::from_utf8(sample.into_inner().ok()?).ok()?;
+
+    let shown = row_count.min(options.rows as u64);
+    Some(format!(
+        "{}: {} rows, {} columns\n{}\nfirst {} of {} rows:\n{}",
+        header,
+        group_thousands(row_count),
+        columns.len(),
+        aligned(&rows),
+        shown,
+        group_thousands(row_count),
+        sample,
+    ))
+}
+
+/// The inferred type and samples of one CSV column.
+#[derive(Default)]
+struct Column {
+    kinds: Vec<&'static str>,
+    samples: Vec<String>,
+}
+
+impl Column {
+    /// Records one field of the column.
+    fn add(&mut self, field: &str, max_samples: usize) {
+        let kind = if field.is_empty() {
+            "empty"
+        } else if field.parse::<i64>().is_ok() {
+            "integer"
+        } else if field.parse::<f64>().is_ok() {
+            "float"
+        } else if field.eq_ignore_ascii_case("true") || field.eq_ignore_ascii_case("false") {
+            "boolean"
+        } else {
+            "string"
+        };
+        add_kind(&mut self.kinds, kind);
+        if !field.is_empty() {
+            add_sample(&mut self.samples, truncated(field), max_samples);
+        }
+    }
+}
+
+/// The inferred shape of every value found at one path of a document.
+#[derive(Default)]
+struct Shape {
+    /// The types seen, in order of first appearance.
+    kinds: Vec<&'static str>,
+    /// Distinct scalar samples, formatted as JSON.
+    samples: Vec<String>,
+    /// Object keys in order of first appearance, with the shape of their values.
+    fields: Vec<(String, Shape)>,
+    /// Whether keys beyond the first `MAX_FIELDS` were left out.
+    extra_fields: bool,
+    /// The shape of array items.
+    items: Option<Box<Shape>>,
+    /// The smallest and largest array length seen.
+    lengths: Option<(usize, usize)>,
+    /// The smallest and largest object key count seen.
+    key_counts: Option<(usize, usize)>,
+}
+
+impl Shape {
+    /// Merges `value` into the shape.
+    fn add(&mut self, value: &Value, max_samples: usize) {
+        let kind = match value {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(n) if n.is_f64() => "float",
+            Value::Number(_) => "integer",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        add_kind(&mut self.kinds, kind);
+
+        match value {
+            Value::Array(items) => {
+                self.lengths = Some(widen(self.lengths, items.len()));
+                let shape = self.items.get_or_insert_with(Box::default);
+                for item in items {
+                    shape.add(item, max_samples);
+                }
+            }
+            Value::Object(map) => {
+                self.key_counts = Some(widen(self.key_counts, map.len()));
+                for (key, item) in map {
+                    match self.fields.iter().position(|(name, _)| name == key) {
+                        Some(index) => self.fields[index].1.add(item, max_samples),
+                        None if self.fields.len() < MAX_FIELDS => {
+                            let mut shape = Shape::default();
+                            shape.add(item, max_samples);
+                            self.fields.push((key.clone(), shape));
+                        }
+                        None => self.extra_fields = true,
+                    }
+                }
+            }
+            Value::Null => {}
+            scalar => add_sample(&mut self.samples, truncated(&scalar.to_string()), max_samples),
+        }
+    }
+
+    /// Appends a `[path, types, samples]` row for this shape and each nested one.
+    fn rows(&self, path: String, rows: &mut Vec<[String; 3]>) {
+        let kinds: Vec<String> = self
+            .kinds
+            .iter()
+            .map(|kind| match *kind {
+                "array" => format!("array ({})", counted(self.lengths, "item")),
+                "object" => format!("object ({})", counted(self.key_counts, "key")),
+                other => other.to_string(),
+            })
+            .collect();
+        rows.push([path.clone(), kinds.join(" | "), self.samples.join(", ")]);
+
+        for (key, shape) in &self.fields {
+            let is_identifier = key.chars().all(|c| c.is_alphanumeric() || c == '_') && !key.is_empty();
+            let child = if is_identifier { format!("{}.{}", path, key) } else { format!("{}[{}]", path, Value::String(key.clone())) };
+            shape.rows(child, rows);
+        }
+        if self.extra_fields {
+            rows.push([format!("{}.…", path), "more keys".to_string(), String::new()]);
+        }
+        if let Some(items) = &self.items {
+            if !items.kinds.is_empty() {
+                items.rows(format!("{}[]", path), rows);
+            }
+        }
+    }
+}
+
+/// Records `kind` in `kinds` unless it is already there.
+fn add_kind(kinds: &mut Vec<&'static str>, kind: &'static str) {
+    if !kinds.contains(&kind) {
+        kinds.push(kind);
+    }
+}
+
+/// Records `sample` in `samples` if it is new and there is room for it.
+fn add_sample(samples: &mut Vec<String>, sample: String, max_samples: usize) {
+    if samples.len() < max_samples && !samples.contains(&sample) {
+        samples.push(sample);
+    }
+}
+
+/// Cuts `text` to `MAX_SAMPLE_CHARS` characters, ending it with `…` when cut.
+fn truncated(text: &str) -> String {
+    match text.char_indices().nth(MAX_SAMPLE_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Extends the `(min, max)` range in `range` to include `n`.
+fn widen(range: Option<(usize, usize)>, n: usize) -> (usize, usize) {
+    range.map_or((n, n), |(min, max)| (min.min(n), max.max(n)))
+}
+
+/// Formats a count or a range of counts, e.g. `3 items` or `0–5 keys`.
+fn counted(range: Option<(usize, usize)>, noun: &str) -> String {
+    match range.unwrap_or_default() {
+        (1, 1) => format!("1 {}", noun),
+        (min, max) if min == max => format!("{} {}s", group_thousands(min as u64), noun),
+        (min, max) => format!("{}–{} {}s", group_thousands(min as u64), group_thousands(max as u64), noun),
+    }
+}
+
+/// Lays out `[name, types, samples]` rows in aligned columns.
+fn aligned(rows: &[[String; 3]]) -> String {
+    let width = |column: usize, cap: usize| rows.iter().map(|row| row[column].chars().count()).filter(|&w| w <= cap).max().unwrap_or(0);
+    let name_width = width(0, MAX_PATH_WIDTH);
+    let kind_width = width(1, usize::MAX);
+
+    let mut text = String::new();
+    for [name, kinds, samples] in rows {
+        let line = format!("{:name_width$}  {:kind_width$}  {}", name, kinds, samples, name_width = name_width, kind_width = kind_width);
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Converts parsed YAML documents into a JSON value, wrapping several documents in an array.
+fn yaml_to_json(mut documents: Vec<Yaml>) -> Value {
+    fn convert(yaml: Yaml) -> Value {
+        match yaml {
+            Yaml::Real(real) => real.parse::<f64>().ok().and_then(Number::from_f64).map_or(Value::String(real), Value::Number),
+            Yaml::Integer(integer) => Value::Number(integer.into()),
+            Yaml::String(string) => Value::String(string),
+            Yaml::Boolean(boolean) => Value::Bool(boolean),
+            Yaml::Array(items) => Value::Array(items.into_iter().map(convert).collect()),
+            Yaml::Hash(hash) => {
+                let map: Map<String, Value> = hash
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Yaml::String(key) | Yaml::Real(key) => key,
+                            Yaml::Integer(key) => key.to_string(),
+                            Yaml::Boolean(key) => key.to_string(),
+                            _ => "~".to_string(),
+                        };
+                        (key, convert(value))
+                    })
+                    .collect();
+                Value::Object(map)
+            }
+            Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => Value::Null,
+        }
+    }
+
+    match documents.len() {
+        1 => convert(documents.remove(0)),
+        _ => Value::Array(documents.into_iter().map(convert).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Summarizes `text` as `format` with three rows and two samples.
+    fn summary(text: &str, format: DataFormat) -> String {
+        let options = SummaryOptions { rows: 3, samples: 2, ..SummaryOptions::default() };
+        summarize(text.as_bytes(), format, text.len() as u64, &options).unwrap()
+    }
+
+    #[test]
+    fn test_summarize_csv() {
+        let csv: String = "id,name,price,note\n".to_string() + &(1..=10).map(|n| format!("{},item {},{}.5,\n", n, n, n)).collect::<String>();
+        assert_eq!(
+            summary(&csv, DataFormat::Csv),
+            format!(
+                "# CSV summary of {} bytes: 10 rows, 4 columns\n\
+                 id     integer  1, 2\n\
+                 name   string   item 1, item 2\n\
+                 price  float    1.5, 2.5\n\
+                 note   empty\n\
+                 \n\
+                 first 3 of 10 rows:\n\
+                 id,name,price,note\n1,item 1,1.5,\n2,item 2,2.5,\n3,item 3,3.5,\n",
+                csv.len()
+            )
+        );
+    }
+
+    #[test]
+    fn test_summarize_json_schema() {
+        let json = r#"{"version": "1.0", "items": [{"id": 1, "tags": ["a"]}, {"id": 2, "price": 9.5, "tags": []}, {"id": 3, "tags": ["b", "c"]}]}"#;
+        let summary = summary(json, DataFormat::Json);
+        let lines: Vec<&str> = summary.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "$                 object (2 keys)",
+                "$.items           array (3 items)",
+                "$.items[]         object (2–3 keys)",
+                "$.items[].id      integer            1, 2",
+                "$.items[].tags    array (0–2 items)",
+                "$.items[].tags[]  string             \"a\", \"b\"",
+                "$.items[].price   float              9.5",
+                "$.version         string             \"1.0\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summarize_yaml_and_toml() {
+        let yaml = summary("name: demo\nports:\n  - 80\n  - 443\n", DataFormat::Yaml);
+        assert!(yaml.starts_with("# YAML summary of"));
+        assert!(yaml.contains("$.ports[]  integer          80, 443\n"), "{}", yaml);
+
+        let toml = summary("[package]\nname = \"demo\"\n", DataFormat::Toml);
+        assert!(toml.contains("$.package.name  string          \"demo\"\n"), "{}", toml);
+    }
+
+    #[test]
+    fn test_summarize_rejects_malformed_content() {
+        assert_eq!(summarize(&b"{not json"[..], DataFormat::Json, 9, &SummaryOptions::default()), None);
+    }
+
+    #[test]
+    fn test_truncated_samples() {
+        assert_eq!(truncated(&"x".repeat(50)), format!("{}…", "x".repeat(40)));
+    }
+}
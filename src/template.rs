@@ -0,0 +1,285 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use chrono::{DateTime, Local};
+use crate::formatter::{FileInfo, OutputFormatter};
+use crate::types::ContentEncoding;
+
+/// Variables available in the `preamble` section.
+const DOCUMENT_VARIABLES: &[&str] = &["directory"];
+
+/// Variables available in the `header` section.
+const FILE_VARIABLES: &[&str] = &["directory", "index", "path", "extension", "syntax", "size", "lines", "mtime", "encoding", "charset"];
+
+/// Variables available in the `body` section.
+const BODY_VARIABLES: &[&str] = &["directory", "index", "path", "extension", "syntax", "size", "lines", "mtime", "encoding", "charset", "content"];
+
+/// Variables available in the `footer` section.
+const FOOTER_VARIABLES: &[&str] = &["directory", "count"];
+
+/// A piece of a template section: literal text or a `{{variable}}` placeholder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// A user-defined output layout parsed from a template file.
+///
+/// A template consists of up to four sections, each wrapped in a block tag:
+///
+/// ```text
+/// {{#preamble}}
+/// # Snapshot of {{directory}}
+/// {{/preamble}}
+/// {{#header}}
+/// ### {{index}}. {{path}} ({{size}} bytes, {{lines}} lines)
+/// {{/header}}
+/// {{#body}}
+/// ~~~{{syntax}}
+/// {{content}}
+/// ~~~
+/// {{/body}}
+/// {{#footer}}
+/// {{count}} files
+/// {{/footer}}
+/// ```
+///
+/// The newline directly after an opening tag is dropped, so each section
+/// renders exactly the lines written between its tags. Sections that are
+/// omitted render nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Template {
+    preamble: Vec<Segment>,
+    header: Vec<Segment>,
+    body: Vec<Segment>,
+    footer: Vec<Segment>,
+}
+
+impl Template {
+    /// Parses a template from its source text.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error naming the line if a tag is unterminated,
+    /// a section is unknown, repeated or never closed, text appears outside a
+    /// section, or a section uses a variable that is not available to it.
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let mut template = Template::default();
+        let mut seen = Vec::new();
+        let mut rest = source;
+
+        loop {
+            // Only whitespace may appear between sections
+            let Some(open) = rest.find("{{") else {
+                if !rest.trim().is_empty() {
+                    return Err(template_error(source, rest, "text outside of a section"));
+                }
+                break;
+            };
+            if !rest[..open].trim().is_empty() {
+                return Err(template_error(source, rest, "text outside of a section"));
+            }
+
+            let tag_start = &rest[open..];
+            let (tag, after) = split_tag(source, tag_start)?;
+            let name = tag.strip_prefix('#').ok_or_else(|| {
+                template_error(source, tag_start, &format!("expected a section such as `{{{{#header}}}}`, found `{{{{{}}}}}`", tag))
+            })?;
+            let allowed = match name {
+                "preamble" => DOCUMENT_VARIABLES,
+                "header" => FILE_VARIABLES,
+                "body" => BODY_VARIABLES,
+                "footer" => FOOTER_VARIABLES,
+                _ => return Err(template_error(source, tag_start, &format!("unknown section `{}`", name))),
+            };
+            if seen.contains(&name) {
+                return Err(template_error(source, tag_start, &format!("section `{}` appears more than once", name)));
+            }
+            seen.push(name);
+
+            // Drop the line break that follows the opening tag
+            let after = after.strip_prefix("\r\n").or_else(|| after.strip_prefix('\n')).unwrap_or(after);
+            let (segments, remaining) = parse_section(source, after, name, allowed)?;
+            match name {
+                "preamble" => template.preamble = segments,
+                "header" => template.header = segments,
+                "body" => template.body = segments,
+                _ => template.footer = segments,
+            }
+            rest = remaining;
+        }
+
+        Ok(template)
+    }
+
+    /// Reads and parses a template file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not parse.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let source = fs::read_to_string(path)?;
+        Template::parse(&source).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Parses the segments of one section up to its closing tag.
+///
+/// Returns the segments and the text following the closing tag.
+fn parse_section<'a>(source: &str, mut rest: &'a str, name: &str, allowed: &[&str]) -> io::Result<(Vec<Segment>, &'a str)> {
+    let mut segments = Vec::new();
+
+    loop {
+        let Some(open) = rest.find("{{") else {
+            return Err(template_error(source, rest, &format!("section `{}` is never closed", name)));
+        };
+        if open > 0 {
+            segments.push(Segment::Text(rest[..open].to_string()));
+        }
+
+        let tag_start = &rest[open..];
+        let (tag, after) = split_tag(source, tag_start)?;
+        if let Some(closing) = tag.strip_prefix('/') {
+            if closing != name {
+                return Err(template_error(source, tag_start, &format!("expected `{{{{/{}}}}}`, found `{{{{/{}}}}}`", name, closing)));
+            }
+            return Ok((segments, after));
+        }
+        if !allowed.contains(&tag) {
+            return Err(template_error(
+                source,
+                tag_start,
+                &format!("unknown variable `{}` in section `{}`, expected one of: {}", tag, name, allowed.join(", ")),
+            ));
+        }
+        segments.push(Segment::Variable(tag.to_string()));
+        rest = after;
+    }
+}
+
+/// Splits `{{ tag }}` off the start of `text`, returning the trimmed tag and
+/// the text after it.
+fn split_tag<'a>(source: &str, text: &'a str) -> io::Result<(&'a str, &'a str)> {
+    let close = text.find("}}").ok_or_else(|| template_error(source, text, "unterminated `{{`"))?;
+    Ok((text[2..close].trim(), &text[close + 2..]))
+}
+
+/// Builds an `InvalidData` error pointing at the line where `at` begins in `source`.
+fn template_error(source: &str, at: &str, message: &str) -> io::Error {
+    let offset = source.len() - at.len();
+    let line = source[..offset].matches('\n').count() + 1;
+    io::Error::new(io::ErrorKind::InvalidData, format!("template line {}: {}", line, message))
+}
+
+/// Renders `segments`, substituting variables through `lookup`.
+fn render(output: &mut dyn Write, segments: &[Segment], lookup: &dyn Fn(&str) -> String) -> io::Result<()> {
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => output.write_all(text.as_bytes())?,
+            Segment::Variable(name) => output.write_all(lookup(name).as_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes the output through a user-defined `Template`.
+///
+/// The preamble is rendered once, the header and body for every file, and
+/// the footer once at the end with the number of files written.
+#[derive(Debug, Clone)]
+pub struct TemplateFormatter {
+    template: Template,
+    directory: String,
+    count: usize,
+}
+
+impl TemplateFormatter {
+    /// Creates a formatter rendering through `template`.
+    pub fn new(template: Template) -> Self {
+        TemplateFormatter { template, directory: String::new(), count: 0 }
+    }
+
+    /// Returns the value of a per-file variable; any other name resolves to the directory.
+    fn file_variable(&self, name: &str, file: &FileInfo, content: Option<&str>) -> String {
+        match name {
+            "index" => file.index.to_string(),
+            "path" => file.path.display().to_string(),
+            "extension" => file.path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default(),
+            "syntax" => file.syntax_name(),
+            "size" => file.size.to_string(),
+            "lines" => file.line_count.to_string(),
+            "mtime" => file.modified.map(|t| DateTime::<Local>::from(t).to_rfc3339()).unwrap_or_default(),
+            "encoding" => match file.encoding {
+                ContentEncoding::Utf8 => "utf-8",
+                ContentEncoding::NonUtf8 => "non-utf-8",
+                ContentEncoding::Base64 => "base64",
+                ContentEncoding::Hexdump => "hexdump",
+            }.to_string(),
+            "charset" => file.charset.unwrap_or("UTF-8").to_string(),
+            "content" => content.unwrap_or_default().to_string(),
+            _ => self.directory.clone(),
+        }
+    }
+}
+
+impl OutputFormatter for TemplateFormatter {
+    fn begin_document(&mut self, output: &mut dyn Write, directory: &Path) -> io::Result<()> {
+        self.directory = directory.display().to_string();
+        render(output, &self.template.preamble, &|_| self.directory.clone())
+    }
+
+    fn begin_file(&mut self, output: &mut dyn Write, file: &FileInfo) -> io::Result<()> {
+        render(output, &self.template.header, &|name| self.file_variable(name, file, None))
+    }
+
+    fn write_content(&mut self, output: &mut dyn Write, file: &FileInfo, content: &str) -> io::Result<()> {
+        render(output, &self.template.body, &|name| self.file_variable(name, file, Some(content)))
+    }
+
+    fn end_file(&mut self, _output: &mut dyn Write, _file: &FileInfo) -> io::Result<()> {
+        self.count += 1;
+        Ok(())
+    }
+
+    fn end_document(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        let count = self.count.to_string();
+        render(output, &self.template.footer, &|name| if name == "count" { count.clone() } else { self.directory.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sections() {
+        let template = Template::parse("{{#preamble}}\n# {{ directory }}\n{{/preamble}}\n\n{{#body}}\n{{content}}\n{{/body}}\n").unwrap();
+
+        assert_eq!(template.preamble, vec![
+            Segment::Text("# ".to_string()),
+            Segment::Variable("directory".to_string()),
+            Segment::Text("\n".to_string()),
+        ]);
+        assert_eq!(template.body, vec![Segment::Variable("content".to_string()), Segment::Text("\n".to_string())]);
+        assert!(template.header.is_empty());
+        assert!(template.footer.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("{{#header}}{{content}}{{/header}}", "unknown variable `content`"),
+            ("{{#header}}{{path}}", "never closed"),
+            ("{{#heading}}{{/heading}}", "unknown section"),
+            ("stray\n{{#body}}{{/body}}", "outside of a section"),
+            ("{{#body}}{{/body}}{{#body}}{{/body}}", "more than once"),
+            ("{{#body}}\n\n{{path}\n{{/body}}", "line 3"),
+        ];
+        for (source, expected) in cases {
+            let err = Template::parse(source).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().contains(expected), "{}: {}", source, err);
+        }
+    }
+}
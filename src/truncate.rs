@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read};
+use crate::types::TruncateOptions;
+
+/// The result of streaming a file through `read_excerpt`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Excerpt {
+    /// The file was within the limits; these are all of its bytes.
+    Complete(Vec<u8>),
+    /// The file was over a limit and was cut to its head and tail.
+    Truncated {
+        /// The head, an elision marker line and the tail.
+        text: String,
+        /// The number of lines in the whole file.
+        line_count: usize,
+        /// The number of lines in `text` before the marker, counting a cut line.
+        head_lines: usize,
+        /// The number of lines in `text` after the marker.
+        tail_lines: usize,
+    },
+}
+
+/// Streams `reader` line by line, cutting content that exceeds the limits in
+/// `options` down to its first and last lines.
+///
+/// Memory stays bounded by the limits: the whole content is only buffered
+/// while it is still within them, and once a limit is crossed only the head
+/// and a ring of the most recent lines are kept while the rest is counted and
+/// dropped. With `max_bytes` set, no more than that is buffered from any one
+/// line, so a single multi-megabyte line is never held whole. At most `head_lines` lines are kept at the start and `tail_lines`
+/// at the end, both clamped to `max_lines`, and when `max_bytes` is set the
+/// head and tail may each use at most half of it. A single line too long for
+/// the head is cut at a character boundary. The omitted part is replaced with
+/// a marker line such as `… 48,213 lines omitted …`, or
+/// `… 1,048,576 bytes omitted …` when a line was only partially shown.
+///
+/// The content is expected to be UTF-8; invalid sequences in the kept lines
+/// of a truncated file are replaced with U+FFFD.
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails.
+pub fn read_excerpt<R: BufRead>(mut reader: R, options: &TruncateOptions) -> io::Result<Excerpt> {
+    let head_limit = options.max_lines.map_or(options.head_lines, |max| options.head_lines.min(max));
+    let tail_limit = options.max_lines.map_or(options.tail_lines, |max| options.tail_lines.min(max - head_limit));
+    let budget = options.max_bytes.map_or(u64::MAX, |max| max / 2);
+
+    // Everything read so far, kept only until a limit is crossed
+    let mut complete = Some(Vec::new());
+    let mut head = Vec::new();
+    let mut head_lines = 0;
+    let mut head_closed = false;
+    let mut partial_line = false;
+    let mut tail: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut tail_bytes = 0u64;
+    let mut line_count = 0;
+    let mut total_bytes = 0u64;
+
+    // A line longer than `max_bytes` is over the limit however it ends, so at
+    // most one byte more than that is buffered and the rest is only counted
+    let line_cap = options.max_bytes.map_or(u64::MAX, |max| max.saturating_add(1));
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let line_len = read_line_capped(&mut reader, &mut line, line_cap)?;
+        if line_len == 0 {
+            break;
+        }
+        line_count += 1;
+        total_bytes += line_len;
+
+        if let Some(bytes) = &mut complete {
+            bytes.extend_from_slice(&line);
+            let over_lines = options.max_lines.is_some_and(|max| line_count > max);
+            let over_bytes = options.max_bytes.is_some_and(|max| total_bytes > max);
+            if over_lines || over_bytes {
+                complete = None;
+            }
+        }
+
+        // Fill the head first, then keep a ring of the most recent lines for the tail
+        if !head_closed {
+            if head_lines < head_limit && (head.len() + line.len()) as u64 <= budget {
+                head.extend_from_slice(&line);
+                head_lines += 1;
+                continue;
+            }
+            head_closed = true;
+            if head.is_empty() && head_limit > 0 && line_len > budget {
+                let cut = floor_char_boundary(&line, budget as usize);
+                head.extend_from_slice(&line[..cut]);
+                head.push(b'\n');
+                partial_line = true;
+                continue;
+            }
+        }
+
+        // A line cut at the cap is longer than the tail's budget on its own
+        if line_len > line.len() as u64 {
+            tail.clear();
+            tail_bytes = 0;
+            continue;
+        }
+        tail.push_back(line.clone());
+        tail_bytes += line.len() as u64;
+        while tail.len() > tail_limit || tail_bytes > budget {
+            let dropped = tail.pop_front().unwrap_or_default();
+            tail_bytes -= dropped.len() as u64;
+        }
+    }
+
+    if let Some(bytes) = complete {
+        return Ok(Excerpt::Complete(bytes));
+    }
+
+    let shown_lines = head_lines + tail.len() + usize::from(partial_line);
+    let marker = if partial_line {
+        let shown_bytes = (head.len() - 1) as u64 + tail_bytes;
+        format!("… {} bytes omitted …\n", group_thousands(total_bytes - shown_bytes))
+    } else {
+        format!("… {} lines omitted …\n", group_thousands((line_count - shown_lines) as u64))
+    };
+
+    let mut text = String::from_utf8_lossy(&head).into_owned();
+    text.push_str(&marker);
+    for line in &tail {
+        text.push_str(&String::from_utf8_lossy(line));
+    }
+    Ok(Excerpt::Truncated { text, line_count, head_lines: head_lines + usize::from(partial_line), tail_lines: tail.len() })
+}
+
+/// Reads one line into `line`, keeping at most `cap` bytes of it.
+///
+/// The rest of a longer line is consumed and counted without being buffered.
+///
+/// # Returns
+///
+/// The full length of the line in bytes, including its newline, or `0` at the
+/// end of the input.
+///
+/// # Errors
+///
+/// Returns an error if reading from `reader` fails.
+fn read_line_capped<R: BufRead>(reader: &mut R, line: &mut Vec<u8>, cap: u64) -> io::Result<u64> {
+    let kept = reader.by_ref().take(cap).read_until(b'\n', line)? as u64;
+    if kept < cap || line.last() == Some(&b'\n') {
+        return Ok(kept);
+    }
+
+    let mut skipped = 0u64;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        let (used, done) = match buffer.iter().position(|&b| b == b'\n') {
+            Some(newline) => (newline + 1, true),
+            None => (buffer.len(), false),
+        };
+        reader.consume(used);
+        skipped += used as u64;
+        if done {
+            break;
+        }
+    }
+    Ok(kept + skipped)
+}
+
+/// Returns the largest index no greater than `index` that does not split a
+/// UTF-8 sequence in `bytes`.
+fn floor_char_boundary(bytes: &[u8], index: usize) -> usize {
+    let mut index = index.min(bytes.len());
+    // Continuation bytes have the form 0b10xxxxxx
+    while index > 0 && index < bytes.len() && bytes[index] & 0xC0 == 0x80 {
+        index -= 1;
+    }
+    index
+}
+
+/// Formats `n` with commas between groups of three digits, e.g. `48,213`.
+pub fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds limits with the given maximums and head and tail sizes.
+    fn limits(max_bytes: Option<u64>, max_lines: Option<usize>, head_lines: usize, tail_lines: usize) -> TruncateOptions {
+        TruncateOptions { max_bytes, max_lines, head_lines, tail_lines }
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(48213), "48,213");
+        assert_eq!(group_thousands(1048576), "1,048,576");
+    }
+
+    #[test]
+    fn test_read_excerpt_within_limits() {
+        let excerpt = read_excerpt(&b"a\nb\nc"[..], &limits(Some(5), Some(3), 1, 1)).unwrap();
+        assert_eq!(excerpt, Excerpt::Complete(b"a\nb\nc".to_vec()));
+    }
+
+    #[test]
+    fn test_read_excerpt_max_lines() {
+        let content: String = (1..=100).map(|n| format!("line {}\n", n)).collect();
+        let excerpt = read_excerpt(content.as_bytes(), &limits(None, Some(10), 3, 2)).unwrap();
+        assert_eq!(excerpt, Excerpt::Truncated {
+            text: "line 1\nline 2\nline 3\n… 95 lines omitted …\nline 99\nline 100\n".to_string(),
+            line_count: 100,
+            head_lines: 3,
+            tail_lines: 2,
+        });
+    }
+
+    #[test]
+    fn test_read_excerpt_clamps_to_max_lines() {
+        let excerpt = read_excerpt(&b"1\n2\n3\n4\n5\n"[..], &limits(None, Some(3), 50, 20)).unwrap();
+        assert_eq!(excerpt, Excerpt::Truncated { text: "1\n2\n3\n… 2 lines omitted …\n".to_string(), line_count: 5, head_lines: 3, tail_lines: 0 });
+    }
+
+    #[test]
+    fn test_read_excerpt_max_bytes_cuts_long_line() {
+        let content = format!("{}\n", "é".repeat(100));
+        let excerpt = read_excerpt(content.as_bytes(), &limits(Some(11), None, 5, 5)).unwrap();
+        // Half of the byte budget is five bytes, which ends inside the third `é`
+        assert_eq!(excerpt, Excerpt::Truncated { text: "éé\n… 197 bytes omitted …\n".to_string(), line_count: 1, head_lines: 1, tail_lines: 0 });
+    }
+
+    #[test]
+    fn test_read_excerpt_caps_long_lines() {
+        // Each huge line is read at most one byte past `max_bytes`
+        let content = format!("short\n{}\n{}\nend\n", "x".repeat(100_000), "y".repeat(100_000));
+        let excerpt = read_excerpt(content.as_bytes(), &limits(Some(20), None, 5, 5)).unwrap();
+        assert_eq!(excerpt, Excerpt::Truncated {
+            text: "short\n… 2 lines omitted …\nend\n".to_string(),
+            line_count: 4,
+            head_lines: 1,
+            tail_lines: 1,
+        });
+
+        let mut line = Vec::new();
+        let mut reader = &b"abcdef\ngh"[..];
+        assert_eq!(read_line_capped(&mut reader, &mut line, 3).unwrap(), 7);
+        assert_eq!(line, b"abc");
+        line.clear();
+        assert_eq!(read_line_capped(&mut reader, &mut line, 3).unwrap(), 2);
+        assert_eq!(line, b"gh");
+    }
+}
@@ -1,166 +1,183 @@
+use std::collections::BTreeSet;
 use std::fmt;
-use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::rules::Rule;
 
-/// Represents a file path to exclude during directory traversal.
-/// `ExcludeItem` provides a convenient abstraction for defining
-/// paths that should not be processed.
-#[derive(Debug)]
-pub struct ExcludeItem(PathBuf);
+/// The exclude rules applied during directory traversal.
+///
+/// Each `Rule` is a literal path, a glob or a `re:` regular expression, see
+/// `Rule::new`. Together with an `IncludeList` the rules form one ordered
+/// list in which the last rule matching a path decides, see `rules::decide`.
+#[derive(Debug, Clone)]
+pub struct ExcludeList(pub Vec<Rule>);
 
-impl ExcludeItem {
-    /// Constructs a new `ExcludeItem` from a base directory and path.
-    /// Returns `None` if the path cannot be resolved.
-    ///
-    /// This method combines the base directory and path, then canonicalizes it to
-    /// create an exclusion pattern.
-    /// 
-    /// # Arguments
+impl ExcludeList {
+    /// Constructs a new `ExcludeList` from patterns in the order given.
     ///
-    /// * `base_dir` - The base directory to which the path is relative.
-    /// * `path` - The path to exclude.
+    /// The rules take positions from 1 up, after the built-in exclusion of a
+    /// `flatten` entry in `base_dir`. Patterns that are malformed or name a
+    /// path outside `base_dir` are left out; use `try_new` to have them
+    /// reported, or `with_positions` to interleave the rules with include rules.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// * `Some(ExcludeItem)` if the path is successfully canonicalized.
-    /// * `None` if canonicalization fails.
-    pub fn new(base_dir: &Path, path: PathBuf) -> Option<Self> {
-        // Canonicalize the path to ensure a consistent absolute reference.
-        // This helps avoid mismatches caused by relative paths or symbolic links.
-        let full_path = base_dir.join(path);
-        canonicalize(full_path).ok().map(|p| {
-            let rel_path = p.strip_prefix(base_dir).unwrap_or(&p).to_path_buf();
-            let pattern = format!("!{}", rel_path.display());
-            PathBuf::from(pattern)
-        }).map(ExcludeItem)
+    /// * `base_dir` - The base directory for resolving exclude paths.
+    /// * `excludes` - The patterns to exclude.
+    pub fn new(base_dir: &Path, excludes: Vec<PathBuf>) -> Self {
+        let rules = excludes.into_iter().enumerate().filter_map(|(i, p)| Rule::new(base_dir, &p, i + 1).ok());
+        Self::with_rules(base_dir, rules.collect())
     }
-}
 
-/// Represents a file path to explicitly include during directory traversal.
-/// Similar to `ExcludeItem`, `IncludeItem` is used to define paths that should
-/// be included in the flattened directory output.
-#[derive(Debug)]
-pub struct IncludeItem(PathBuf);
-
-impl IncludeItem {
-    /// Constructs a new `IncludeItem` from a base directory and path.
-    /// Returns `None` if the path cannot be resolved.
+    /// Constructs a new `ExcludeList` from patterns in the order given,
+    /// failing on the first malformed one.
     ///
     /// # Arguments
     ///
-    /// * `base_dir` - The base directory to which the path is relative.
-    /// * `path` - The path to include.
+    /// * `base_dir` - The base directory for resolving exclude paths.
+    /// * `excludes` - The patterns to exclude.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// `Some(IncludeItem)` if the path is valid, `None` otherwise.
-    pub fn new(base_dir: &Path, path: PathBuf) -> Option<Self> {
-        let full_path = base_dir.join(path);
-        canonicalize(full_path).ok().map(|p| {
-            let rel_path = p.strip_prefix(base_dir).unwrap_or(&p).to_path_buf();
-            PathBuf::from(rel_path)
-        }).map(IncludeItem)
+    /// Returns an `InvalidInput` error if a pattern is malformed, see `Rule::new`.
+    pub fn try_new(base_dir: &Path, excludes: Vec<PathBuf>) -> io::Result<Self> {
+        Self::with_positions(base_dir, excludes.into_iter().enumerate().map(|(i, p)| (i + 1, p)).collect())
     }
-}
 
-/// A collection of paths to exclude during directory traversal.
-///
-/// `ExcludeList` is used to specify a list of paths that should be ignored,
-/// providing methods to check if a path is within the excluded items.
-#[derive(Debug)]
-pub struct ExcludeList(pub Vec<PathBuf>);
-
-
-impl ExcludeList {
-    /// Constructs a new `ExcludeList` based on a set of exclude paths.
+    /// Constructs a new `ExcludeList` from patterns with explicit positions,
+    /// e.g. their indices on the command line.
     ///
     /// # Arguments
     ///
     /// * `base_dir` - The base directory for resolving exclude paths.
-    /// * `excludes` - A vector of relative paths to exclude.
-    pub fn new(base_dir: &Path, excludes: Vec<PathBuf>) -> Self {
+    /// * `excludes` - The patterns to exclude, each with its position among all rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a pattern is malformed, see `Rule::new`.
+    pub fn with_positions(base_dir: &Path, excludes: Vec<(usize, PathBuf)>) -> io::Result<Self> {
+        let rules = excludes
+            .into_iter()
+            .map(|(position, pattern)| Rule::new(base_dir, &pattern, position))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::with_rules(base_dir, rules))
+    }
+
+    /// Puts the built-in exclusion of a `flatten` entry before the user's rules.
+    fn with_rules(base_dir: &Path, rules: Vec<Rule>) -> Self {
         let mut list = Vec::new();
 
-        // Add default excludes
-        // list.push(ExcludeItem::new(base_dir, PathBuf::from("flatten*")));
-        if let Some(default_excludes) = ExcludeItem::new(base_dir, PathBuf::from("flatten")) {
-            list.push(default_excludes.0);
+        // Add default excludes, which come before every user rule and are never reported as unused
+        let default_exclude = PathBuf::from("flatten");
+        if base_dir.join(&default_exclude).exists() {
+            list.extend(Rule::new(base_dir, &default_exclude, 0).ok().map(Rule::into_builtin));
         }
 
         // Add user-specified excludes
-        list.extend(excludes.into_iter().filter_map(|p| ExcludeItem::new(base_dir, p).map(|item| item.0)));
+        list.extend(rules);
 
         ExcludeList(list)
     }
 
-    // @TODO: Implement contains method or remove it
-    // Is this for testing or library?
-    /// Checks if a specific path is within the exclusion list.
-    /// 
-    /// This method is used to verify if a path should be ignored during directory
-    /// traversal, based on predefined exclusion criteria.
-    /// 
+    /// Checks if a path is matched by any exclude rule.
+    ///
+    /// This does not consider include rules, which may still select the
+    /// path; use `rules::decide` for the combined verdict.
+    ///
     /// # Arguments
-    /// 
-    /// * `path` - The path to check for exclusion.
-    /// 
+    ///
+    /// * `path` - The path to check, relative to the base directory.
+    ///
     /// # Returns
-    /// 
-    /// * `true` if the path is in the exclusion list, `false` otherwise.
-    #[allow(dead_code)]
+    ///
+    /// * `true` if an exclude rule matches the path or one of its parent directories.
     pub fn contains(&self, path: &Path) -> bool {
-        // Converts `path` to `PathBuf` for easier matching with stored exclusion paths
-        self.0.contains(&path.to_path_buf())
+        self.0.iter().any(|rule| rule.matches(path))
     }
 }
 
-/// A collection of paths to include during directory traversal.
+/// The include rules applied during directory traversal.
 ///
-/// `IncludeList` is used to specify a list of paths that should be
-/// explicitly included, even if they would otherwise be excluded.
-#[derive(Debug)]
-pub struct IncludeList(pub Vec<PathBuf>);
+/// When any include rule is given, only paths whose last matching rule is an
+/// include are selected. A literal include path is also walked even if it is
+/// hidden or ignored.
+#[derive(Debug, Clone)]
+pub struct IncludeList(pub Vec<Rule>);
 
 impl IncludeList {
-    /// Constructs a new `IncludeList` based on a set of include paths.
+    /// Constructs a new `IncludeList` from patterns in the order given.
+    ///
+    /// The rules take positions from 1 up. Patterns that are malformed or name
+    /// a path outside `base_dir` are left out; use `try_new` to have them
+    /// reported, or `with_positions` to interleave the rules with exclude rules.
     ///
     /// # Arguments
     ///
     /// * `base_dir` - The base directory for resolving include paths.
-    /// * `includes` - A vector of relative paths to include.
+    /// * `includes` - The patterns to include.
     pub fn new(base_dir: &Path, includes: Vec<PathBuf>) -> Self {
-        let list: Vec<PathBuf> = includes.into_iter()
-            .filter_map(|p| IncludeItem::new(base_dir, p).map(|item| item.0))
-            .collect();
-
-        IncludeList(list)
-    }
-    
-    // @TODO: Implement contains method or remove it
-    /// Checks if a specific path is within the exclusion list.
-    /// 
-    /// This method is used to verify if a path should be ignored during directory
-    /// traversal, based on predefined exclusion criteria.
-    /// 
+        IncludeList(includes.into_iter().enumerate().filter_map(|(i, p)| Rule::new(base_dir, &p, i + 1).ok()).collect())
+    }
+
+    /// Constructs a new `IncludeList` from patterns in the order given,
+    /// failing on the first malformed one.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_dir` - The base directory for resolving include paths.
+    /// * `includes` - The patterns to include.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a pattern is malformed, see `Rule::new`.
+    pub fn try_new(base_dir: &Path, includes: Vec<PathBuf>) -> io::Result<Self> {
+        Self::with_positions(base_dir, includes.into_iter().enumerate().map(|(i, p)| (i + 1, p)).collect())
+    }
+
+    /// Constructs a new `IncludeList` from patterns with explicit positions,
+    /// e.g. their indices on the command line.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_dir` - The base directory for resolving include paths.
+    /// * `includes` - The patterns to include, each with its position among all rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a pattern is malformed, see `Rule::new`.
+    pub fn with_positions(base_dir: &Path, includes: Vec<(usize, PathBuf)>) -> io::Result<Self> {
+        let list = includes
+            .into_iter()
+            .map(|(position, pattern)| Rule::new(base_dir, &pattern, position))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(IncludeList(list))
+    }
+
+    /// Checks if a path is matched by any include rule.
+    ///
+    /// This does not consider exclude rules, which may still drop the path;
+    /// use `rules::decide` for the combined verdict.
+    ///
     /// # Arguments
-    /// 
-    /// * `path` - The path to check for exclusion.
-    /// 
+    ///
+    /// * `path` - The path to check, relative to the base directory.
+    ///
     /// # Returns
-    /// 
-    /// * `true` if the path is in the exclusion list, `false` otherwise.
-    #[allow(dead_code)]
+    ///
+    /// * `true` if an include rule matches the path or one of its parent directories.
     pub fn contains(&self, path: &Path) -> bool {
-        // Converts `path` to `PathBuf` for easier matching with stored inclusion paths
-        self.0.contains(&path.to_path_buf())
+        self.0.iter().any(|rule| rule.matches(path))
     }
 }
 
 impl fmt::Display for ExcludeList {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "ExcludeList: [")?;
-        for path in &self.0 {
-            write!(f, "{}, ", path.display())?;
+        for rule in &self.0 {
+            write!(f, "{}, ", rule.pattern)?;
         }
         write!(f, "]")
     }
@@ -169,61 +186,503 @@ impl fmt::Display for ExcludeList {
 impl fmt::Display for IncludeList {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "IncludeList: [")?;
-        for path in &self.0 {
-            write!(f, "{}, ", path.display())?;
+        for rule in &self.0 {
+            write!(f, "{}, ", rule.pattern)?;
         }
         write!(f, "]")
     }
 }
 
-/// Generates a canonicalized path to ensure consistency and prevent
-/// mismatches due to relative paths or symbolic links.
-/// 
-/// This function resolves `..`, `.`, and symbolic links in the given path,
-/// producing an absolute and normalized path. It’s particularly useful
-/// for ensuring that inclusion/exclusion rules are reliably applied.
-/// 
-/// # Arguments
-/// 
-/// * `path` - The path to be canonicalized.
-/// 
-/// # Returns
-/// 
-/// * `Ok(PathBuf)` - The canonicalized path on success.
-/// * `Err(io::Error)` - An error if the path cannot be resolved.
+/// The layout used when writing flattened content to the output file.
 ///
-/// # Errors
+/// `Markdown` is the historical `## path` header plus fenced block layout,
+/// while `Json` and `Jsonl` emit one `FileRecord` per file for tooling that
+/// needs to load snapshots without scraping Markdown. `Xml` wraps each file
+/// in `<document>` elements for use in LLM prompts, `Html` renders a
+/// self-contained, syntax-highlighted report, `Shar` writes a shell script
+/// that recreates the files, and `Patch` writes a diff for `git apply`.
+/// `Tar`, `TarGz` and `Zip` are binary bundles of the raw files with an
+/// embedded `MANIFEST.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// `## path` headers followed by a fenced, syntax-labelled block.
+    #[default]
+    Markdown,
+    /// A single JSON document with a top-level `files` array.
+    Json,
+    /// One JSON object per line, one line per file.
+    Jsonl,
+    /// A root `<documents>` element with one escaped `<document>` per file.
+    Xml,
+    /// A single offline HTML page with highlighted, line-numbered files.
+    Html,
+    /// A self-extracting POSIX `sh` archive.
+    Shar,
+    /// A git patch creating every file from `/dev/null`.
+    Patch,
+    /// An uncompressed tar archive of the selected files.
+    Tar,
+    /// A gzip-compressed tar archive of the selected files.
+    TarGz,
+    /// A zip archive of the selected files.
+    Zip,
+}
+
+impl OutputFormat {
+    /// All accepted `--format` values, in the order shown in `--help`.
+    pub const VARIANTS: &'static [&'static str] = &["markdown", "json", "jsonl", "xml", "html", "shar", "patch", "tar", "tar.gz", "zip"];
+
+    /// Returns the file extension used for timestamped default output names.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Html => "html",
+            OutputFormat::Shar => "sh",
+            OutputFormat::Patch => "patch",
+            OutputFormat::Tar => "tar",
+            OutputFormat::TarGz => "tar.gz",
+            OutputFormat::Zip => "zip",
+        }
+    }
+
+    /// Returns `true` for the binary bundle formats, which are written by
+    /// `bundle::write_bundle` rather than an `OutputFormatter`.
+    pub fn is_archive(&self) -> bool {
+        matches!(self, OutputFormat::Tar | OutputFormat::TarGz | OutputFormat::Zip)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" => Ok(OutputFormat::Markdown),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "xml" => Ok(OutputFormat::Xml),
+            "html" => Ok(OutputFormat::Html),
+            "shar" => Ok(OutputFormat::Shar),
+            "patch" => Ok(OutputFormat::Patch),
+            "tar" => Ok(OutputFormat::Tar),
+            "tar.gz" => Ok(OutputFormat::TarGz),
+            "zip" => Ok(OutputFormat::Zip),
+            other => Err(format!("unknown output format `{}`", other)),
+        }
+    }
+}
+
+/// Describes how a file's bytes were decoded before being written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentEncoding {
+    /// The file was valid UTF-8, or was transcoded to UTF-8, and its content is included as text.
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// The file was binary and a placeholder was written in place of its content.
+    #[serde(rename = "non-utf-8")]
+    NonUtf8,
+    /// The file was binary and its content is included as standard base64.
+    #[serde(rename = "base64")]
+    Base64,
+    /// The file was binary and its content is included as an `xxd`-style hex dump.
+    #[serde(rename = "hexdump")]
+    Hexdump,
+}
+
+/// What to do with binary files, i.e. files with a NUL byte or the magic
+/// number of a known binary format near the start, or that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryPolicy {
+    /// Leave binary files out of the output entirely.
+    Skip,
+    /// Write a one-line placeholder with the size, MIME guess and SHA-256 digest.
+    #[default]
+    Placeholder,
+    /// Embed the content as standard base64.
+    Base64,
+    /// Embed the content as an `xxd`-style hex dump.
+    Hexdump,
+}
+
+impl BinaryPolicy {
+    /// All accepted `--binary` values, in the order shown in `--help`.
+    pub const VARIANTS: &'static [&'static str] = &["skip", "placeholder", "base64", "hexdump"];
+}
+
+impl FromStr for BinaryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(BinaryPolicy::Skip),
+            "placeholder" => Ok(BinaryPolicy::Placeholder),
+            "base64" => Ok(BinaryPolicy::Base64),
+            "hexdump" => Ok(BinaryPolicy::Hexdump),
+            other => Err(format!("unknown binary policy `{}`", other)),
+        }
+    }
+}
+
+/// A single flattened file as emitted by the structured output formats.
+///
+/// Each record carries the path relative to the flattened directory, the
+/// syntax name detected by `syntect`, the size in bytes, and the content when
+/// it could be decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    /// Path relative to the flattened directory.
+    pub path: String,
+    /// Lowercased syntax name, e.g. `rust` or `plain text`.
+    pub syntax: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Whether `content` holds the decoded file text, base64, a hex dump, or nothing.
+    pub encoding: ContentEncoding,
+    /// The file content, or `None` when the file was binary and was omitted.
+    pub content: Option<String>,
+    /// The guessed MIME type of a binary file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Lowercase hex SHA-256 digest of a binary file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The legacy encoding a text file was transcoded from, e.g. `Shift_JIS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    /// Whether malformed sequences were replaced with U+FFFD while transcoding.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lossy: bool,
+    /// Whether `content` was cut to a head and tail around an elision marker.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Whether `content` is an outline of the file's declarations rather than the whole text.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub outline: bool,
+    /// How `content` was rendered from the file instead of being its text, e.g. `notebook`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversion: Option<String>,
+    /// The number of lines in the whole file, recorded when `content` is line-numbered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<usize>,
+}
+
+/// One file listed in a bundle's `MANIFEST.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path of the file inside the bundle, relative to the flattened directory.
+    pub path: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// Lowercase hex SHA-256 digest of the file content.
+    pub sha256: String,
+}
+
+/// The selection settings recorded in a bundle's `MANIFEST.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectionSettings {
+    /// The include patterns, as written.
+    pub include: Vec<String>,
+    /// The exclude patterns, as written, including the default `flatten` exclude.
+    pub exclude: Vec<String>,
+    /// Whether hidden files were processed.
+    pub allow_hidden: bool,
+    /// The ignore files that were read. Manifests written before this was
+    /// recorded read as the default of every kind.
+    #[serde(default)]
+    pub ignore: IgnoreOptions,
+}
+
+/// The contents of the `MANIFEST.json` embedded in tar and zip bundles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// The bundled files in archive order.
+    pub files: Vec<ManifestEntry>,
+    /// The include/exclude settings that produced the selection.
+    pub settings: SelectionSettings,
+}
+
+/// Options controlling how `process_files_with_options` selects and writes files.
 ///
-/// Returns an error if the path cannot be accessed, likely due to missing
-/// permissions or invalid path segments.
-fn canonicalize<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf> {
-    let path = path.as_ref();
-    let components = path.components();
-    
-    // Start from root if path is absolute, otherwise initialize with a blank PathBuf
-    let mut result = if path.is_absolute() {
-        PathBuf::from("/")
-    } else {
-        PathBuf::new()
+/// The `Default` implementation reproduces the behaviour of `process_files`
+/// with hidden files skipped and Markdown output.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    /// Whether hidden files should be processed.
+    pub allow_hidden: bool,
+    /// Which ignore files the walk honours.
+    pub ignore: IgnoreOptions,
+    /// Limits the selection to the paths `git::changes` reported, if set.
+    pub git: Option<GitChanges>,
+    /// The layout of the generated output file.
+    pub format: OutputFormat,
+    /// The syntect theme used by `OutputFormat::Html`, or `None` for the default.
+    pub theme: Option<String>,
+    /// A template file that replaces the built-in layout selected by `format`.
+    pub template: Option<PathBuf>,
+    /// How binary files are written.
+    pub binary: BinaryPolicy,
+    /// Whether text in a legacy encoding that does not decode cleanly is decoded
+    /// with U+FFFD replacements instead of being treated as binary.
+    pub lossy: bool,
+    /// Per-file limits beyond which text is cut to its head and tail.
+    pub truncate: TruncateOptions,
+    /// Whether every line of text content is prefixed with its line number.
+    pub line_numbers: bool,
+    /// Whether comments are stripped from text content, and which are kept.
+    pub comments: CommentOptions,
+    /// Whether text content is reduced to an outline of its declarations.
+    pub outline: bool,
+    /// The number of output lines kept per Jupyter notebook code cell, or `None`
+    /// to drop outputs.
+    pub notebook_outputs: Option<usize>,
+    /// Whether and from which size CSV, TSV, JSON, YAML and TOML files are summarized.
+    pub summary: SummaryOptions,
+    /// The number of rows written per table when a SQLite database is dumped
+    /// in place of its placeholder.
+    pub sqlite_rows: usize,
+    /// Whether detected secrets are written as is instead of being redacted.
+    /// They are reported in the `ProcessReport` either way.
+    pub keep_secrets: bool,
+}
+
+/// Selects the ignore files honoured while walking the directory.
+///
+/// The `Default` implementation reads every kind, like `git` plus `.ignore`
+/// and `.flattenignore` files. Patterns in later kinds are overridden by
+/// earlier ones: `.flattenignore`, then `.ignore`, then `.gitignore`, then
+/// `.git/info/exclude`, then the global gitignore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoreOptions {
+    /// Whether `.flattenignore` files are read.
+    pub flattenignore: bool,
+    /// Whether `.ignore` files are read.
+    pub dot_ignore: bool,
+    /// Whether `.gitignore` files are read inside git repositories.
+    pub gitignore: bool,
+    /// Whether the global gitignore named by git's `core.excludesFile` is read.
+    pub global_gitignore: bool,
+    /// Whether `.git/info/exclude` is read.
+    pub git_exclude: bool,
+    /// Whether ignore files in the parent directories of the walked directory are read.
+    pub parents: bool,
+}
+
+impl IgnoreOptions {
+    /// Options that read no ignore files at all.
+    pub const NONE: IgnoreOptions = IgnoreOptions {
+        flattenignore: false,
+        dot_ignore: false,
+        gitignore: false,
+        global_gitignore: false,
+        git_exclude: false,
+        parents: false,
     };
+}
 
-    for component in components {
-        match component {
-            // Root and current directory components don't alter the path structure
-            std::path::Component::RootDir => {}
-            std::path::Component::CurDir => {}
-            std::path::Component::ParentDir => {
-                // Remove the last component for each `..` in the path
-                result.pop();
-            }
-            // Normal component represents a directory or file name to append
-            std::path::Component::Normal(name) => {
-                result.push(name);
-            }
-            _ => {}
+impl Default for IgnoreOptions {
+    fn default() -> Self {
+        IgnoreOptions {
+            flattenignore: true,
+            dot_ignore: true,
+            gitignore: true,
+            global_gitignore: true,
+            git_exclude: true,
+            parents: true,
         }
     }
+}
+
+/// Limits the selection to the paths git reports as changed.
+///
+/// Each mode adds the paths it reports, so setting several selects their
+/// union. With no mode set, which is the default, git is not consulted. The
+/// include, exclude, ignore-file and hidden-file settings still apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitSelection {
+    /// A commit, branch or tag. Files whose working-copy content differs from
+    /// it, whether committed, staged or not, are selected.
+    pub changed_since: Option<String>,
+    /// Whether files with changes staged in the index are selected. Their
+    /// working-copy content is flattened, not the staged version.
+    pub staged: bool,
+    /// Whether files git neither tracks nor ignores are selected.
+    pub untracked: bool,
+}
+
+impl GitSelection {
+    /// Returns `true` if any mode is set, so git decides which files are selected.
+    pub fn is_active(&self) -> bool {
+        self.changed_since.is_some() || self.staged || self.untracked
+    }
+}
+
+/// The paths a `GitSelection` picks out, relative to the flattened directory.
+///
+/// Read once with `git::changes` and passed to every walk through
+/// `ProcessOptions::git`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitChanges {
+    /// The changed files that exist in the working copy.
+    pub paths: BTreeSet<PathBuf>,
+    /// The changed files that were deleted, so cannot be flattened.
+    pub deleted: BTreeSet<PathBuf>,
+}
+
+/// Controls comment stripping for token-constrained output.
+///
+/// The `Default` implementation leaves comments untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommentOptions {
+    /// Whether comments and docstrings are removed.
+    pub strip: bool,
+    /// Whether doc comments and docstrings survive stripping.
+    pub keep_doc: bool,
+    /// Whether a copyright or license comment at the top of a file survives stripping.
+    pub keep_license: bool,
+}
 
-    // Perform the final canonicalization to resolve symbolic links and normalize the path
-    fs::canonicalize(result)
+/// Controls the summaries written in place of large data files.
+///
+/// Summarizing is disabled unless `enabled` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryOptions {
+    /// Whether data files are summarized.
+    pub enabled: bool,
+    /// Only data files larger than this many bytes are summarized.
+    pub min_bytes: u64,
+    /// The number of leading CSV and TSV rows kept.
+    pub rows: usize,
+    /// The number of distinct sample values shown per column or path.
+    pub samples: usize,
+}
+
+impl SummaryOptions {
+    /// The default size above which data files are summarized.
+    pub const DEFAULT_MIN_BYTES: u64 = 64 * 1024;
+
+    /// The default number of leading CSV and TSV rows kept.
+    pub const DEFAULT_ROWS: usize = 5;
+
+    /// The default number of sample values per column or path.
+    pub const DEFAULT_SAMPLES: usize = 3;
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        SummaryOptions {
+            enabled: false,
+            min_bytes: Self::DEFAULT_MIN_BYTES,
+            rows: Self::DEFAULT_ROWS,
+            samples: Self::DEFAULT_SAMPLES,
+        }
+    }
+}
+
+/// Per-file size limits beyond which text content is cut to a head and a tail
+/// around an elision marker.
+///
+/// Truncation is disabled while both `max_bytes` and `max_lines` are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncateOptions {
+    /// Files larger than this many bytes are truncated.
+    pub max_bytes: Option<u64>,
+    /// Files with more than this many lines are truncated.
+    pub max_lines: Option<usize>,
+    /// The number of lines kept from the start of a truncated file.
+    pub head_lines: usize,
+    /// The number of lines kept from the end of a truncated file.
+    pub tail_lines: usize,
+}
+
+impl TruncateOptions {
+    /// The default number of lines kept from the start of a truncated file.
+    pub const DEFAULT_HEAD_LINES: usize = 50;
+
+    /// The default number of lines kept from the end of a truncated file.
+    pub const DEFAULT_TAIL_LINES: usize = 20;
+
+    /// Returns `true` if any limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.max_lines.is_some()
+    }
+}
+
+impl Default for TruncateOptions {
+    fn default() -> Self {
+        TruncateOptions {
+            max_bytes: None,
+            max_lines: None,
+            head_lines: Self::DEFAULT_HEAD_LINES,
+            tail_lines: Self::DEFAULT_TAIL_LINES,
+        }
+    }
+}
+
+/// What `unflatten` should do when a file it is about to restore already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Refuse to restore anything if any target file already exists.
+    #[default]
+    Fail,
+    /// Replace existing files with the flattened content.
+    Overwrite,
+    /// Leave existing files untouched and restore only the missing ones.
+    SkipExisting,
+}
+
+/// Options controlling how `unflatten` recreates files from a flattened document.
+#[derive(Debug, Clone, Default)]
+pub struct UnflattenOptions {
+    /// Report what would be written without touching the filesystem.
+    pub dry_run: bool,
+    /// How to handle files that already exist under the target directory.
+    pub conflict: ConflictPolicy,
+}
+
+/// A single file parsed back out of a flattened Markdown document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlattenedEntry {
+    /// Path relative to the directory that was flattened.
+    pub path: PathBuf,
+    /// The fence label, i.e. the lowercased syntax name.
+    pub syntax: String,
+    /// The original file content, or `None` if it was written as `<non-UTF-8 data>`
+    /// or converted, e.g. to an outline.
+    pub content: Option<String>,
+}
+
+/// A secret found while flattening, as listed in a `ProcessReport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    /// Path relative to the flattened directory.
+    pub path: PathBuf,
+    /// The detector that matched, e.g. `private-key` or `github-token`.
+    pub kind: &'static str,
+    /// The 1-based line of the original file the secret starts on.
+    pub line: usize,
+}
+
+/// The outcome of a `process_files_with_options` run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessReport {
+    /// Secrets found in the flattened files, in output order. They were replaced
+    /// with `[REDACTED:kind]` unless `ProcessOptions::keep_secrets` was set.
+    pub redactions: Vec<Redaction>,
+}
+
+/// The outcome of an `unflatten` run.
+///
+/// In dry-run mode `written` lists the files that would have been written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnflattenReport {
+    /// Files written (or that would be written) under the target directory.
+    pub written: Vec<PathBuf>,
+    /// Files left untouched because they already existed.
+    pub skipped: Vec<PathBuf>,
+    /// Entries that were flattened as `<non-UTF-8 data>`, converted, e.g. to an
+    /// outline, or truncated, and cannot be restored.
+    pub unrestorable: Vec<PathBuf>,
 }
@@ -0,0 +1,421 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use encoding_rs::Encoding;
+use crate::file_processing::NON_UTF8_PLACEHOLDER;
+use crate::formatter::{COMMENTS_STRIPPED_NOTE, OUTLINE_NOTE, TRANSCODED_PREFIX, TRUNCATED_NOTE};
+use crate::line_numbers::strip_gutter;
+use crate::{notebook, sqlite, summary};
+use crate::types::{ConflictPolicy, FlattenedEntry, UnflattenOptions, UnflattenReport};
+
+/// Parses a flattened Markdown document back into its individual files.
+///
+/// The document is expected to use the layout written by `process_files`: a
+/// `## path` header followed by a fenced block per file. Fences may use any
+/// number (at least three) of backticks or tildes, and a block is only closed
+/// by a fence of the same character that is at least as long as the opening one.
+/// Files that were transcoded from a legacy encoding are restored as UTF-8,
+/// while binary placeholders and files whose header marks them as converted,
+/// such as outlines, stripped copies and truncated excerpts, have no content
+/// to restore.
+///
+/// # Arguments
+///
+/// * `document` - The flattened document text.
+///
+/// # Returns
+///
+/// * `Ok(Vec<FlattenedEntry>)` - The parsed entries in document order.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error naming the offending line if a header is not
+/// followed by a fence, unexpected text appears between entries, or a block is
+/// never closed.
+pub fn parse_flattened(document: &str) -> io::Result<Vec<FlattenedEntry>> {
+    let mut entries = Vec::new();
+    // Lines keep their endings, so files with CRLF line breaks are restored byte for byte
+    let mut lines = document.split_inclusive('\n').map(split_line_ending).enumerate();
+
+    while let Some((number, (line, _))) = lines.next() {
+        // Blank lines separate entries
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let path = line.strip_prefix("## ").ok_or_else(|| {
+            invalid_data(number, format!("expected a `## path` header, found `{}`", line))
+        })?;
+        let (path, numbered, converted) = strip_header_note(path);
+
+        // The header must be followed immediately by an opening fence
+        let (fence_number, (fence_line, _)) = lines
+            .next()
+            .ok_or_else(|| invalid_data(number, format!("missing fenced block for `{}`", path)))?;
+        let (fence_char, fence_len) = parse_fence(fence_line)
+            .ok_or_else(|| invalid_data(fence_number, format!("expected an opening fence, found `{}`", fence_line)))?;
+        let syntax = fence_line[fence_len..].trim().to_string();
+
+        // Collect the body until a matching closing fence
+        let mut body = String::new();
+        let mut closed = false;
+        for (_, (body_line, ending)) in lines.by_ref() {
+            if is_closing_fence(body_line, fence_char, fence_len) {
+                closed = true;
+                break;
+            }
+            let body_line = if numbered { strip_gutter(body_line).unwrap_or(body_line) } else { body_line };
+            body.push_str(body_line);
+            body.push_str(ending);
+        }
+        if !closed {
+            return Err(invalid_data(fence_number, format!("fenced block for `{}` is never closed", path)));
+        }
+
+        // `process_files` appends a newline after the content, so drop exactly one
+        body.pop();
+        // Binary files were written as a single placeholder line, possibly followed by details
+        let placeholder = body.starts_with(NON_UTF8_PLACEHOLDER) && !body.contains('\n');
+        let content = if placeholder || converted { None } else { Some(body) };
+
+        entries.push(FlattenedEntry {
+            path: PathBuf::from(path),
+            syntax,
+            content,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Splits a line produced by `split_inclusive('\n')` into its text and its
+/// ending, which is `\r\n`, `\n` or empty for an unterminated last line.
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let text = line.strip_suffix('\n').map_or(line, |text| text.strip_suffix('\r').unwrap_or(text));
+    (text, &line[text.len()..])
+}
+
+/// Splits the note written after the path in a header, such as
+/// ` (412 lines, transcoded from Shift_JIS)`, off a header.
+///
+/// The note is only removed when every part of it is one `flatten` writes and
+/// any encoding it names is known to `encoding_rs`, so a path that merely ends
+/// in parentheses is left alone.
+///
+/// # Returns
+///
+/// The path, whether the note records a line count, which means the content
+/// carries a line number gutter, and whether it marks the content as converted
+/// into something other than the file, such as an outline, a copy without
+/// comments, an excerpt of a truncated file, a notebook rendered as Markdown, a data file summary or a
+/// SQLite schema dump.
+fn strip_header_note(header: &str) -> (&str, bool, bool) {
+    let Some((path, note)) = header.strip_suffix(')').and_then(|h| h.rsplit_once(" (")) else {
+        return (header, false, false);
+    };
+
+    let mut numbered = false;
+    let mut converted = false;
+    for part in note.split(", ") {
+        let count = part.strip_suffix(" lines").or_else(|| part.strip_suffix(" line"));
+        if count.is_some_and(|c| !c.is_empty() && c.chars().all(|d| d.is_ascii_digit())) {
+            numbered = true;
+            continue;
+        }
+        let converted_part = matches!(
+            part,
+            OUTLINE_NOTE | COMMENTS_STRIPPED_NOTE | TRUNCATED_NOTE | notebook::CONVERSION | sqlite::CONVERSION | summary::CONVERSION
+        );
+        if converted_part {
+            converted = true;
+            continue;
+        }
+        let known_charset = part
+            .strip_prefix(TRANSCODED_PREFIX)
+            .is_some_and(|charset| Encoding::for_label(charset.as_bytes()).is_some());
+        if !known_charset && part != "lossy" {
+            return (header, false, false);
+        }
+    }
+    (path, numbered, converted)
+}
+
+/// Recreates the files of a flattened document under a target directory.
+///
+/// All entries are parsed and validated before anything is written, so an
+/// unsafe path or an existing file under `ConflictPolicy::Fail` aborts the run
+/// without leaving a partially restored tree behind.
+///
+/// # Arguments
+///
+/// * `input_file` - The flattened Markdown file to read.
+/// * `target_dir` - The directory under which files are recreated.
+/// * `options` - The `UnflattenOptions` selecting dry-run and conflict handling.
+///
+/// # Returns
+///
+/// * `Ok(UnflattenReport)` - The files written, skipped and left unrestorable.
+///
+/// # Errors
+///
+/// Returns an error if the input cannot be read or parsed, an entry uses an
+/// absolute path or `..` traversal, a target file exists under
+/// `ConflictPolicy::Fail`, or a file or directory cannot be created.
+pub fn unflatten(input_file: &Path, target_dir: &Path, options: &UnflattenOptions) -> io::Result<UnflattenReport> {
+    let document = fs::read_to_string(input_file)?;
+    let entries = parse_flattened(&document)?;
+    let mut report = UnflattenReport::default();
+    let mut pending = Vec::new();
+
+    // Validate every entry before touching the filesystem
+    for entry in &entries {
+        validate_relative_path(&entry.path)?;
+
+        let Some(content) = &entry.content else {
+            report.unrestorable.push(entry.path.clone());
+            continue;
+        };
+
+        let target = target_dir.join(&entry.path);
+        if target.exists() {
+            match options.conflict {
+                ConflictPolicy::Fail => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} already exists", target.display()),
+                    ));
+                }
+                ConflictPolicy::SkipExisting => {
+                    report.skipped.push(entry.path.clone());
+                    continue;
+                }
+                ConflictPolicy::Overwrite => {}
+            }
+        }
+        pending.push((target, content));
+        report.written.push(entry.path.clone());
+    }
+
+    if !options.dry_run {
+        for (target, content) in pending {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, content)?;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Ensures a flattened path stays inside the target directory.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the path is empty, absolute, carries a
+/// drive prefix, or contains a `..` component.
+fn validate_relative_path(path: &Path) -> io::Result<()> {
+    let unsafe_path = |reason: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("refusing to restore `{}`: {}", path.display(), reason))
+    };
+
+    if path.as_os_str().is_empty() {
+        return Err(unsafe_path("empty path"));
+    }
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Err(unsafe_path("absolute path")),
+            Component::ParentDir => return Err(unsafe_path("path traversal")),
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns the fence character and length if `line` opens a fenced block.
+fn parse_fence(line: &str) -> Option<(char, usize)> {
+    let fence_char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = line.chars().take_while(|c| *c == fence_char).count();
+    (fence_len >= 3).then_some((fence_char, fence_len))
+}
+
+/// Returns `true` if `line` closes a block opened with `fence_len` `fence_char`s.
+fn is_closing_fence(line: &str, fence_char: char, fence_len: usize) -> bool {
+    let line = line.trim_end();
+    line.len() >= fence_len && line.chars().all(|c| c == fence_char)
+}
+
+/// Builds an `InvalidData` error pointing at a 0-based line index.
+fn invalid_data(line_index: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line_index + 1, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_flattened() {
+        let document = "## src/main.rs\n```rust\nfn main() {}\n\n```\n\n## empty.txt\n```plain text\n\n```\n\n## data.bin\n```plain text\n<non-UTF-8 data>\n```\n\n";
+        let entries = parse_flattened(document).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, PathBuf::from("src/main.rs"));
+        assert_eq!(entries[0].syntax, "rust");
+        assert_eq!(entries[0].content.as_deref(), Some("fn main() {}\n"));
+        assert_eq!(entries[1].content.as_deref(), Some(""));
+        assert_eq!(entries[2].content, None);
+    }
+
+    #[test]
+    fn test_parse_flattened_longer_fences() {
+        // A longer or tilde fence may contain shorter backtick runs
+        let document = "## README.md\n````markdown\n```rust\nlet x = 1;\n```\n\n````\n\n## notes.md\n~~~markdown\n```\n\n~~~\n";
+        let entries = parse_flattened(document).unwrap();
+
+        assert_eq!(entries[0].content.as_deref(), Some("```rust\nlet x = 1;\n```\n"));
+        assert_eq!(entries[1].content.as_deref(), Some("```\n"));
+    }
+
+    #[test]
+    fn test_parse_flattened_crlf() {
+        // Line breaks inside a block are kept as written, while the fences may end in either
+        let document = "## win.txt\r\n```plain text\r\na\r\nb\r\n\n```\r\n\r\n## mixed.txt\n```plain text\na\r\nb\nc\n```\n";
+        let entries = parse_flattened(document).unwrap();
+        assert_eq!(entries[0].path, PathBuf::from("win.txt"));
+        assert_eq!(entries[0].content.as_deref(), Some("a\r\nb\r\n"));
+        assert_eq!(entries[1].content.as_deref(), Some("a\r\nb\nc"));
+    }
+
+    #[test]
+    fn test_parse_flattened_unclosed_block() {
+        let err = parse_flattened("## a.txt\n```plain text\ncontent\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_strip_header_note() {
+        assert_eq!(strip_header_note("legacy.c (transcoded from Shift_JIS)"), ("legacy.c", false, false));
+        assert_eq!(strip_header_note("old.txt (transcoded from windows-1252, lossy)"), ("old.txt", false, false));
+        assert_eq!(strip_header_note("lib.rs (40 lines, outline)"), ("lib.rs", true, true));
+        assert_eq!(strip_header_note("report.ipynb (notebook)"), ("report.ipynb", false, true));
+        assert_eq!(strip_header_note("sales.csv (12 lines, summary)"), ("sales.csv", true, true));
+        assert_eq!(strip_header_note("fixtures.db (sqlite)"), ("fixtures.db", false, true));
+        assert_eq!(strip_header_note("big.log (9000 lines, truncated)"), ("big.log", true, true));
+        assert_eq!(strip_header_note("main.rs (comments stripped)"), ("main.rs", false, true));
+        assert_eq!(strip_header_note("src/types.rs (412 lines)"), ("src/types.rs", true, false));
+        assert_eq!(strip_header_note("a.c (1 line, transcoded from Shift_JIS)"), ("a.c", true, false));
+        assert_eq!(strip_header_note("notes (transcoded from nowhere)"), ("notes (transcoded from nowhere)", false, false));
+        assert_eq!(strip_header_note("photo (2).txt"), ("photo (2).txt", false, false));
+        assert_eq!(strip_header_note("src/main.rs"), ("src/main.rs", false, false));
+    }
+
+    #[test]
+    fn test_parse_flattened_line_numbers() {
+        let document = "## src/main.rs (2 lines)\n```rust\n1 | fn main() {\n2 | }\n\n```\n";
+        let entries = parse_flattened(document).unwrap();
+        assert_eq!(entries[0].path, PathBuf::from("src/main.rs"));
+        assert_eq!(entries[0].content.as_deref(), Some("fn main() {\n}\n"));
+    }
+
+    #[test]
+    fn test_validate_relative_path() {
+        assert!(validate_relative_path(Path::new("src/lib.rs")).is_ok());
+        assert!(validate_relative_path(Path::new("/etc/passwd")).is_err());
+        assert!(validate_relative_path(Path::new("src/../../escape.txt")).is_err());
+        assert!(validate_relative_path(Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_unflatten_conflict_policies() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("flat.txt");
+        fs::write(&input, "## a.txt\n```plain text\nnew a\n\n```\n\n## dir/b.txt\n```plain text\nnew b\n\n```\n\n").unwrap();
+
+        let target = temp_dir.path().join("out");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a.txt"), "old a\n").unwrap();
+
+        // Fail refuses before writing anything
+        let err = unflatten(&input, &target, &UnflattenOptions::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!target.join("dir/b.txt").exists());
+
+        // Dry run reports without writing
+        let options = UnflattenOptions { dry_run: true, conflict: ConflictPolicy::Overwrite };
+        let report = unflatten(&input, &target, &options).unwrap();
+        assert_eq!(report.written.len(), 2);
+        assert!(!target.join("dir/b.txt").exists());
+
+        // Skip-existing leaves a.txt alone
+        let options = UnflattenOptions { dry_run: false, conflict: ConflictPolicy::SkipExisting };
+        let report = unflatten(&input, &target, &options).unwrap();
+        assert_eq!(report.skipped, vec![PathBuf::from("a.txt")]);
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "old a\n");
+        assert_eq!(fs::read_to_string(target.join("dir/b.txt")).unwrap(), "new b\n");
+
+        // Overwrite replaces it
+        let options = UnflattenOptions { dry_run: false, conflict: ConflictPolicy::Overwrite };
+        unflatten(&input, &target, &options).unwrap();
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "new a\n");
+    }
+
+    #[test]
+    fn test_unflatten_skips_outlines() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("flat.txt");
+        fs::write(&input, "## lib.rs (outline)\n```rust\npub fn run() { … }\n\n```\n\n## notes.txt\n```plain text\nkept\n\n```\n\n").unwrap();
+
+        // An outline is not the file, so restoring it would write a stub over the source
+        let target = temp_dir.path().join("out");
+        let report = unflatten(&input, &target, &UnflattenOptions::default()).unwrap();
+        assert_eq!(report.unrestorable, vec![PathBuf::from("lib.rs")]);
+        assert_eq!(report.written, vec![PathBuf::from("notes.txt")]);
+        assert!(!target.join("lib.rs").exists());
+    }
+
+    #[test]
+    fn test_unflatten_skips_rendered_notebooks() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("flat.txt");
+        fs::write(&input, "## report.ipynb (notebook)\n````markdown\nIntro\n\n```python\nprint(42)\n```\n\n````\n").unwrap();
+
+        // The rendered Markdown is not valid notebook JSON, so the .ipynb is left alone
+        let target = temp_dir.path().join("out");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("report.ipynb"), "{\"cells\": []}").unwrap();
+        let options = UnflattenOptions { dry_run: false, conflict: ConflictPolicy::Overwrite };
+        let report = unflatten(&input, &target, &options).unwrap();
+        assert_eq!(report.unrestorable, vec![PathBuf::from("report.ipynb")]);
+        assert_eq!(fs::read_to_string(target.join("report.ipynb")).unwrap(), "{\"cells\": []}");
+    }
+
+    #[test]
+    fn test_unflatten_skips_data_summaries() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("flat.txt");
+        fs::write(&input, "## sales.csv (summary)\n```plain text\n# CSV summary of 70,000 bytes\n\n```\n\n").unwrap();
+
+        let target = temp_dir.path().join("out");
+        let report = unflatten(&input, &target, &UnflattenOptions::default()).unwrap();
+        assert_eq!(report.unrestorable, vec![PathBuf::from("sales.csv")]);
+        assert!(report.written.is_empty());
+        assert!(!target.join("sales.csv").exists());
+    }
+
+    #[test]
+    fn test_unflatten_refuses_traversal() {
+        let temp_dir = tempdir().unwrap();
+        let input = temp_dir.path().join("flat.txt");
+        fs::write(&input, "## ok.txt\n```plain text\nok\n```\n\n## ../evil.txt\n```plain text\nevil\n```\n\n").unwrap();
+
+        let target = temp_dir.path().join("out");
+        let err = unflatten(&input, &target, &UnflattenOptions::default()).unwrap_err();
+
+        // Nothing is written when any entry is unsafe
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!target.exists());
+        assert!(!temp_dir.path().join("evil.txt").exists());
+    }
+}
@@ -1,8 +1,9 @@
-use std::fs::{File};
+use std::fs::{self, File};
 use std::io::Write;
 use tempfile::tempdir;
 use flatten::file_processing::{process_files, calculate_directory_size};
 use flatten::types::{ExcludeList, IncludeList};
+use flatten::unflatten::parse_flattened;
 
 #[test]
 fn test_empty_file_processing() {
@@ -96,3 +97,42 @@ fn test_directory_size_with_large_file() {
     // Verify the size matches the large file's size
     assert_eq!(size, large_file_path.metadata().unwrap().len());
 }
+
+#[test]
+fn test_readme_style_files_roundtrip() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path().canonicalize().unwrap();
+
+    // README-style inputs mixing fence lengths, tildes, inline code and fake headers
+    let inputs = [
+        ("README.md", "# Title\n\n```bash\ncargo build\n```\n"),
+        ("docs/nested.md", "````markdown\n```rust\nlet x = 1;\n```\n````\n"),
+        ("docs/tildes.md", "~~~\n```\n~~~\n"),
+        ("src/lib.rs", "/// ```\n/// assert!(true);\n/// ```\npub fn f() {}\n"),
+        ("notes.txt", "## fake/header.txt\n```plain text\nnot a file\n```\n"),
+        ("windows.txt", "a\r\nb\r\n"),
+        ("mixed.txt", "a\r\nb\nlast\r"),
+    ];
+    fs::create_dir_all(dir_path.join("docs")).unwrap();
+    fs::create_dir_all(dir_path.join("src")).unwrap();
+    for (path, content) in inputs {
+        fs::write(dir_path.join(path), content).unwrap();
+    }
+
+    // Flatten the tree and parse the output back
+    let output_dir = tempdir().unwrap();
+    let output_file = output_dir.path().join("output.txt");
+    let exclude = ExcludeList::new(&dir_path, vec![]);
+    let include = IncludeList::new(&dir_path, vec![]);
+    process_files(&dir_path, &output_file, &exclude, &include, true).unwrap();
+
+    let output_content = std::fs::read_to_string(output_file).unwrap();
+    let entries = parse_flattened(&output_content).unwrap();
+
+    // Every file comes back as exactly one entry with its original content
+    assert_eq!(entries.len(), inputs.len());
+    for (path, content) in inputs {
+        let entry = entries.iter().find(|e| e.path == std::path::Path::new(path)).unwrap();
+        assert_eq!(entry.content.as_deref(), Some(content));
+    }
+}
@@ -1,8 +1,8 @@
 use std::fs::{self, File};
 use std::io::Write;
 use tempfile::tempdir;
-use flatten::file_processing::calculate_directory_size;
-use flatten::types::{ExcludeList, IncludeList};
+use flatten::file_processing::{calculate_directory_size, calculate_directory_size_with_options, FLATTEN_IGNORE_FILE};
+use flatten::types::{ExcludeList, IgnoreOptions, IncludeList, ProcessOptions};
 
 #[test]
 // #[ignore]
@@ -34,9 +34,7 @@ fn test_ignore_excludes_files() {
     assert_eq!(calc_size, expected_size);
 }
 
-// @ToDO get this test working but the functionality is working as expected
 #[test]
-#[ignore]
 fn test_gitignore_excludes_files() {
     let temp_dir = tempdir().unwrap();
     let dir_path = temp_dir.path().canonicalize().unwrap();
@@ -53,6 +51,8 @@ fn test_gitignore_excludes_files() {
     let gitignore_path = dir_path.join(".gitignore");
     let mut gitignore = File::create(&gitignore_path).unwrap();
     writeln!(gitignore, "test2.rs").unwrap();
+    // .gitignore files only apply inside a git repository
+    fs::create_dir(dir_path.join(".git")).unwrap();
 
     // Calculate size, file2 should be ignored
     let exclude = ExcludeList::new(&dir_path, vec![]);
@@ -89,10 +89,7 @@ fn test_including_hidden_files() {
     assert_eq!(calc_size, expected_size);
 }
 
-// @TODO: This test is failing, this functionality does not work as expected
-// because the file path must be explicitly in the include list.
 #[test]
-#[ignore]
 fn test_including_hidden_subdirectory() {
     let temp_dir = tempdir().unwrap();
     let dir_path = temp_dir.path().canonicalize().unwrap();
@@ -117,3 +114,51 @@ fn test_including_hidden_subdirectory() {
     // Size should include only the file in the hidden subdirectory
     assert_eq!(calc_size, expected_size);
 }
+
+#[test]
+fn test_flattenignore_overrides_other_ignore_files() {
+    let temp_dir = tempdir().unwrap();
+    let dir_path = temp_dir.path().canonicalize().unwrap();
+    fs::write(dir_path.join("kept.log"), "kept").unwrap();
+    fs::write(dir_path.join("main.rs"), "fn main() {}").unwrap();
+    fs::write(dir_path.join(".ignore"), "*.log\n").unwrap();
+    fs::write(dir_path.join(FLATTEN_IGNORE_FILE), "!kept.log\nmain.rs\n").unwrap();
+
+    let exclude = ExcludeList::new(&dir_path, vec![]);
+    let include = IncludeList::new(&dir_path, vec![]);
+    let calc_size = calculate_directory_size(&dir_path, &exclude, &include, false).unwrap();
+
+    // .flattenignore drops main.rs and brings back the log .ignore excludes
+    assert_eq!(calc_size, 4);
+}
+
+#[test]
+fn test_ignore_file_switches() {
+    let temp_dir = tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let dir_path = root.join("project");
+    fs::create_dir_all(dir_path.join(".git")).unwrap();
+    fs::write(root.join(".ignore"), "parent.txt\n").unwrap();
+    fs::write(dir_path.join("parent.txt"), "1").unwrap();
+    fs::write(dir_path.join("dot.txt"), "22").unwrap();
+    fs::write(dir_path.join("git.txt"), "333").unwrap();
+    fs::write(dir_path.join("flatten.txt"), "4444").unwrap();
+    fs::write(dir_path.join(".ignore"), "dot.txt\n").unwrap();
+    fs::write(dir_path.join(".gitignore"), "git.txt\n").unwrap();
+    fs::write(dir_path.join(FLATTEN_IGNORE_FILE), "flatten.txt\n").unwrap();
+
+    let exclude = ExcludeList::new(&dir_path, vec![]);
+    let include = IncludeList::new(&dir_path, vec![]);
+    let size = |ignore: IgnoreOptions| {
+        let options = ProcessOptions { ignore, ..ProcessOptions::default() };
+        calculate_directory_size_with_options(&dir_path, &exclude, &include, &options).unwrap()
+    };
+
+    assert_eq!(size(IgnoreOptions::default()), 0);
+    assert_eq!(size(IgnoreOptions { parents: false, ..IgnoreOptions::default() }), 1);
+    // The parent's .ignore is switched off too
+    assert_eq!(size(IgnoreOptions { dot_ignore: false, ..IgnoreOptions::default() }), 3);
+    assert_eq!(size(IgnoreOptions { gitignore: false, ..IgnoreOptions::default() }), 3);
+    assert_eq!(size(IgnoreOptions { flattenignore: false, ..IgnoreOptions::default() }), 4);
+    assert_eq!(size(IgnoreOptions::NONE), 10);
+}